@@ -0,0 +1,13 @@
+// Block textures, packed into a grid of tiles in one atlas texture.
+
+uniform sampler2D atlas;
+uniform vec2 atlas_tiles;
+
+// Texture coordinate of `uv` within the atlas tile of block `tile`, counted
+// left to right and top to bottom.
+vec2 atlas_coord(float tile, vec2 uv) {
+    tile = floor(tile + 0.5);
+    vec2 cell = vec2(mod(tile, atlas_tiles.x), floor(tile / atlas_tiles.x));
+    uv = clamp(uv, 0.0, 0.999);
+    return vec2((cell.x + uv.x) / atlas_tiles.x, 1.0 - (cell.y + 1.0 - uv.y) / atlas_tiles.y);
+}
\ No newline at end of file
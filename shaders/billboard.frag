@@ -0,0 +1,17 @@
+#version 330 core
+out vec4 frag_color;
+
+in vec2 f_tex_coord;
+
+uniform vec3 color;
+uniform vec3 sun_color;
+
+void main() {
+    // Shade the disc as if it were a ball lit from the camera.
+    vec2 d = f_tex_coord * 2.0 - 1.0;
+    float r = dot(d, d);
+    if (r > 1.0) {
+        discard;
+    }
+    frag_color = vec4(color * sun_color * (0.5 + 0.5 * sqrt(1.0 - r)), 1.0);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+in vec2 position;
+in vec2 tex_coord;
+
+out vec2 f_tex_coord;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform vec3 center;
+uniform float size;
+
+void main() {
+    // Offset in view space so the quad always faces the camera.
+    gl_Position = perspective * (view * vec4(center, 1.0) + vec4(position * size, 0.0, 0.0));
+    f_tex_coord = tex_coord;
+}
@@ -0,0 +1,11 @@
+#version 330 core
+out vec4 frag_color;
+
+in float shade;
+
+uniform vec3 color;
+uniform vec3 sun_color;
+
+void main() {
+    frag_color = vec4(color * sun_color * shade, 1.0);
+}
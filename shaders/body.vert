@@ -0,0 +1,15 @@
+#version 330 core
+in vec3 position;
+in vec3 normal;
+
+out float shade;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+uniform vec3 sun_direction;
+
+void main() {
+    gl_Position = perspective * view * model * vec4(position, 1.0);
+    shade = 0.4 + 0.6 * clamp(dot(normal, normalize(sun_direction)), 0.0, 1.0);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+
+#define BLOCK_LIGHT_COLOR vec3(1.0, 0.8, 0.55)
+
+out vec4 frag_color;
+
+in vec3 f_tex_coord;
+in vec2 f_uv;
+in float f_tile;
+in vec2 f_light;
+
+uniform sampler3D volume;
+uniform vec3 sun_color;
+
+#include "atlas.glsl"
+
+void main() {
+    vec3 light = max(f_light.x * sun_color, f_light.y * BLOCK_LIGHT_COLOR);
+    vec3 detail = 2.0 * texture(atlas, atlas_coord(f_tile, fract(f_uv))).rgb;
+    frag_color = vec4(light * detail * texture(volume, f_tex_coord).xyz, 1.0);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+in vec2 position;
+in vec3 tex_coord;
+in vec2 uv;
+in float tile;
+in vec2 light;
+
+out vec3 f_tex_coord;
+out vec2 f_uv;
+out float f_tile;
+out vec2 f_light;
+
+uniform mat4 projection;
+
+void main() {
+    gl_Position = projection * vec4(position, 0.0, 1.0);
+    f_tex_coord = tex_coord;
+    f_uv = uv;
+    f_tile = tile;
+    f_light = light;
+}
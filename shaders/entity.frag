@@ -0,0 +1,33 @@
+#version 330 core
+out vec4 frag_color;
+
+in vec2 f_tex_coord;
+
+uniform vec3 color;
+uniform float alpha;
+// 0 for a goal flag, 1 for a key, 2 for the lock of a door.
+uniform int shape;
+
+void main() {
+    vec2 p = f_tex_coord;
+    bool inside;
+    if (shape == 0) {
+        bool pole = p.x > 0.2 && p.x < 0.28 && p.y > 0.05;
+        bool pennant = p.y > 0.55 && p.y < 0.95 && p.x > 0.28
+            && p.x - 0.28 < (1.0 - abs(p.y - 0.75) / 0.2) * 0.55;
+        inside = pole || pennant;
+    } else if (shape == 1) {
+        float ring = length(p - vec2(0.3, 0.5));
+        bool shaft = p.x > 0.45 && p.x < 0.9 && abs(p.y - 0.5) < 0.05;
+        bool bit = p.x > 0.75 && p.x < 0.85 && p.y > 0.3 && p.y < 0.5;
+        inside = (ring < 0.2 && ring > 0.1) || shaft || bit;
+    } else {
+        float shackle = length(p - vec2(0.5, 0.55));
+        bool body = abs(p.x - 0.5) < 0.3 && p.y > 0.15 && p.y < 0.55;
+        inside = body || (shackle < 0.22 && shackle > 0.13 && p.y > 0.55);
+    }
+    if (!inside) {
+        discard;
+    }
+    frag_color = vec4(color, alpha);
+}
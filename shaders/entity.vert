@@ -0,0 +1,14 @@
+#version 330 core
+in vec2 position;
+in vec2 tex_coord;
+
+out vec2 f_tex_coord;
+
+uniform mat4 projection;
+uniform vec2 center;
+uniform float size;
+
+void main() {
+    gl_Position = projection * vec4(center + position * size, 0.0, 1.0);
+    f_tex_coord = tex_coord;
+}
@@ -1,15 +1,17 @@
-#version 330 core
-in vec2 position;
-in vec2 tex_coord;
-
-out vec2 f_tex_coord;
-
-uniform mat4 view;
-uniform mat4 perspective;
-uniform mat4 scale;
-uniform mat4 transform;
-
-void main() {
-    gl_Position = perspective * view * transform * scale * vec4(position, 0.0, 1.0);
-    f_tex_coord = tex_coord;
+#version 330 core
+in vec2 position;
+in vec2 tex_coord;
+in mat4 model;
+in vec4 color;
+
+out vec2 f_tex_coord;
+out vec4 f_color;
+
+uniform mat4 view;
+uniform mat4 perspective;
+
+void main() {
+    gl_Position = perspective * view * model * vec4(position, 0.0, 1.0);
+    f_tex_coord = tex_coord;
+    f_color = color;
 }
\ No newline at end of file
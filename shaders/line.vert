@@ -0,0 +1,9 @@
+#version 330 core
+in vec3 position;
+
+uniform mat4 perspective;
+uniform mat4 view;
+
+void main() {
+    gl_Position = perspective * view * vec4(position, 1.0);
+}
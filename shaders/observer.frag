@@ -0,0 +1,16 @@
+#version 330 core
+out vec4 frag_color;
+
+in vec2 f_tex_coord;
+in vec4 f_color;
+
+void main() {
+    vec2 f = min(f_tex_coord, 1.0 - f_tex_coord);
+
+    // An opaque border around a translucent square.
+    if (min(f.x, f.y) < 0.01) {
+        frag_color = vec4(f_color.rgb, 1.0);
+    } else {
+        frag_color = f_color;
+    }
+}
\ No newline at end of file
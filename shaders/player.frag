@@ -2,8 +2,10 @@
 out vec4 frag_color;
 
 in vec2 f_tex_coord;
+in vec4 f_color;
 uniform sampler2D tex;
 
 void main() {
-    frag_color = vec4(texture(tex, f_tex_coord));
+    vec4 texel = texture(tex, f_tex_coord);
+    frag_color = vec4(texel.rgb * f_color.rgb, texel.a * f_color.a);
 }
\ No newline at end of file
@@ -0,0 +1,12 @@
+#version 330 core
+in vec2 position;
+in vec3 color;
+
+out vec3 f_color;
+
+uniform mat4 projection;
+
+void main() {
+    gl_Position = projection * vec4(position, 0.0, 1.0);
+    f_color = color;
+}
@@ -1,11 +1,16 @@
 #version 330 core
 out vec4 frag_color;
 
-in float brightness;
+in vec3 light;
 in vec3 f_tex_coord;
+in vec2 f_uv;
+in float f_tile;
 
 uniform sampler3D volume;
 
+#include "atlas.glsl"
+
 void main() {
-    frag_color = vec4(brightness * texture(volume, f_tex_coord).xyz, 1.0);
+    vec3 detail = 2.0 * texture(atlas, atlas_coord(f_tile, fract(f_uv))).rgb;
+    frag_color = vec4(light * detail * texture(volume, f_tex_coord).xyz, 1.0);
 }
\ No newline at end of file
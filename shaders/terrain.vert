@@ -1,16 +1,34 @@
 #version 330 core
+
+#define AMBIENT 0.3
+#define BLOCK_LIGHT_COLOR vec3(1.0, 0.8, 0.55)
+
 in vec3 position;
 in vec3 normal;
 in vec3 tex_coord;
+in vec2 uv;
+in float tile;
+in float occlusion;
+in float sky_light;
+in float block_light;
 
 uniform mat4 perspective;
 uniform mat4 view;
+uniform vec3 offset;
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
 
-out float brightness;
+out vec3 light;
 out vec3 f_tex_coord;
+out vec2 f_uv;
+out float f_tile;
 
 void main() {
-    gl_Position = perspective * view * vec4(position, 1.0);
-    brightness = clamp(0.0, 1.0, dot(normal, normalize(vec3(-1.0, 4.0, -1.0))));
+    gl_Position = perspective * view * vec4(position + offset, 1.0);
+    float diffuse = clamp(dot(normal, normalize(sun_direction)), 0.0, 1.0);
+    vec3 sun = sun_color * (AMBIENT + (1.0 - AMBIENT) * diffuse) * sky_light;
+    light = max(sun, BLOCK_LIGHT_COLOR * block_light) * occlusion;
     f_tex_coord = tex_coord;
+    f_uv = uv;
+    f_tile = tile;
 }
\ No newline at end of file
@@ -1,10 +1,19 @@
 #version 330 core
 in vec3 position;
 in vec3 tex_coord;
+in float tile;
+in float sky_light;
+in float block_light;
 
 out vec3 g_tex_coord;
+out float g_tile;
+out vec2 g_light;
+
+uniform vec3 offset;
 
 void main() {
-    gl_Position = vec4(position, 1.0);
+    gl_Position = vec4(position + offset, 1.0);
     g_tex_coord = tex_coord;
+    g_tile = tile;
+    g_light = vec2(sky_light, block_light);
 }
\ No newline at end of file
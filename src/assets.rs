@@ -0,0 +1,349 @@
+//! Loading of shaders and textures, turning driver and file errors into
+//! messages that name the file and line at fault.
+//!
+//! Both are built into the binary, so it runs from any directory. With
+//! `--assets <dir>` they are read from `<dir>/shaders` and `<dir>/assets`
+//! instead, and `Shader` and `Texture` load them again when they change there.
+
+use std::borrow::Cow;
+use std::io::ErrorKind;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use glium::program::{ProgramCreationError, ShaderType};
+use glium::texture::{RawImage2d, Texture2d};
+use glium::Program;
+
+use super::error::{Error, Result};
+
+/// Pairs every file with its contents, included at compile time.
+macro_rules! embed {
+    ($($file:literal),* $(,)?) => {
+        &[$(($file, include_bytes!(concat!("../", $file)))),*]
+    };
+}
+
+/// Files built into the binary, by their path relative to the repository.
+const EMBEDDED: &[(&str, &[u8])] = embed![
+    "shaders/atlas.glsl",
+    "shaders/billboard.frag",
+    "shaders/billboard.vert",
+    "shaders/body.frag",
+    "shaders/body.vert",
+    "shaders/contour.frag",
+    "shaders/contour.vert",
+    "shaders/entity.frag",
+    "shaders/entity.vert",
+    "shaders/instance.vert",
+    "shaders/line.frag",
+    "shaders/line.vert",
+    "shaders/observer.frag",
+    "shaders/plane.frag",
+    "shaders/player.frag",
+    "shaders/section.frag",
+    "shaders/section.vert",
+    "shaders/terrain.frag",
+    "shaders/terrain.vert",
+    "shaders/xsection.frag",
+    "shaders/xsection.geom",
+    "shaders/xsection.vert",
+    "assets/atlas.png",
+    "assets/character.png",
+];
+
+/// Directory given with `--assets`, read in place of the embedded files.
+static DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Makes every later load read from `directory`. Only the first call counts.
+pub fn set_directory(directory: PathBuf) {
+    let _ = DIRECTORY.set(directory);
+}
+
+/// Where `file` is read from, for messages.
+fn path(file: &str) -> PathBuf {
+    match DIRECTORY.get() {
+        Some(directory) => directory.join(file),
+        None => PathBuf::from(file),
+    }
+}
+
+fn exists(file: &str) -> bool {
+    match DIRECTORY.get() {
+        Some(directory) => directory.join(file).exists(),
+        None => EMBEDDED.iter().any(|(name, _)| *name == file),
+    }
+}
+
+fn read(file: &str) -> Result<Cow<'static, [u8]>> {
+    match DIRECTORY.get() {
+        Some(directory) => {
+            let path = directory.join(file);
+            std::fs::read(&path)
+                .map(Cow::Owned)
+                .map_err(|error| Error::Io(path, error))
+        }
+        None => EMBEDDED
+            .iter()
+            .find(|(name, _)| *name == file)
+            .map(|(_, data)| Cow::Borrowed(*data))
+            .ok_or_else(|| Error::Io(path(file), ErrorKind::NotFound.into())),
+    }
+}
+
+fn read_text(file: &str) -> Result<String> {
+    String::from_utf8(read(file)?.into_owned()).map_err(|error| {
+        Error::Io(
+            path(file),
+            std::io::Error::new(ErrorKind::InvalidData, error),
+        )
+    })
+}
+
+/// Last modification time of a file in the `--assets` directory. Embedded
+/// files never change and have none.
+fn modified(file: &str) -> Option<SystemTime> {
+    let path = DIRECTORY.get()?.join(file);
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Line number and message of a line in a shader compile log. Drivers write
+/// `0:12(5): error: ...` (Mesa), `0(12) : error ...` (NVIDIA) or
+/// `ERROR: 0:12: ...` (AMD).
+fn location(line: &str) -> Option<(usize, &str)> {
+    let rest = line
+        .trim_start_matches("ERROR: ")
+        .trim_start_matches("WARNING: ");
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    let rest = rest.strip_prefix(':').or_else(|| rest.strip_prefix('('))?;
+    let end = rest.find(|c: char| !c.is_ascii_digit())?;
+    let number = rest[..end].parse().ok()?;
+    let (_, message) = rest[end..].split_once(':')?;
+    Some((number, message.trim()))
+}
+
+/// Rewrites a compile log so every message starts with `file:line:` and is
+/// followed by the offending line of source.
+fn annotate(path: &Path, source: &str, log: &str) -> String {
+    let mut report = String::new();
+    for line in log.lines().filter(|line| !line.trim().is_empty()) {
+        match location(line) {
+            Some((number, message)) => {
+                report += &format!("{}:{}: {}\n", path.display(), number, message);
+                if let Some(code) = source.lines().nth(number.wrapping_sub(1)) {
+                    report += &format!("    {}\n", code.trim());
+                }
+            }
+            None => report += &format!("{}: {}\n", path.display(), line.trim()),
+        }
+    }
+    report
+}
+
+fn shader_file(name: &str, extension: &str) -> String {
+    format!("shaders/{}.{}", name, extension)
+}
+
+fn texture_file(name: &str) -> String {
+    format!("assets/{}", name)
+}
+
+/// Snippets shared between shaders, such as the atlas lookup, which are
+/// pulled in with `#include "<file>"`.
+fn snippets() -> impl Iterator<Item = &'static str> {
+    EMBEDDED
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| name.ends_with(".glsl"))
+}
+
+/// Replaces every `#include "<file>"` line with the contents of
+/// `shaders/<file>`. A `#line` directive follows each, so the driver counts the
+/// lines after it as they are in the file.
+fn expand(source: &str) -> Result<String> {
+    let mut expanded = String::new();
+    for (index, line) in source.lines().enumerate() {
+        let include = line
+            .trim()
+            .strip_prefix("#include")
+            .map(|rest| rest.trim().trim_matches('"'));
+        match include {
+            Some(file) => {
+                expanded += &read_text(&format!("shaders/{}", file))?;
+                expanded += &format!("\n#line {}\n", index + 2);
+            }
+            None => {
+                expanded += line;
+                expanded += "\n";
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Compiles `shaders/<vertex>.vert` and `shaders/<name>.frag`, along with
+/// `shaders/<name>.geom` where there is one.
+pub fn program(display: &glium::Display, vertex: &str, name: &str) -> Result<Program> {
+    let file = |extension: &str| match extension {
+        "vert" => shader_file(vertex, extension),
+        _ => shader_file(name, extension),
+    };
+    let vertex = read_text(&file("vert"))?;
+    let fragment = read_text(&file("frag"))?;
+    let geometry = if exists(&file("geom")) {
+        Some(read_text(&file("geom"))?)
+    } else {
+        None
+    };
+
+    // Errors are reported against the files as written, before expansion.
+    let expanded = geometry.as_deref().map(expand).transpose()?;
+    Program::from_source(
+        display,
+        &expand(&vertex)?,
+        &expand(&fragment)?,
+        expanded.as_deref(),
+    )
+    .map_err(|error| {
+        Error::Shader(match error {
+            ProgramCreationError::CompilationError(log, stage) => {
+                let (extension, source) = match stage {
+                    ShaderType::Geometry => ("geom", geometry.as_deref().unwrap_or("")),
+                    ShaderType::Fragment => ("frag", fragment.as_str()),
+                    _ => ("vert", vertex.as_str()),
+                };
+                annotate(&path(&file(extension)), source, &log)
+            }
+            ProgramCreationError::LinkingError(log) => {
+                format!(
+                    "{}: linking failed:\n{}",
+                    path(&file("*")).display(),
+                    log.trim()
+                )
+            }
+            error => format!("{}: {}", path(&file("*")).display(), error),
+        })
+    })
+}
+
+/// Loads `assets/<name>` into a texture, bottom row first as OpenGL expects.
+pub fn texture(display: &glium::Display, name: &str) -> Result<Texture2d> {
+    let file = texture_file(name);
+    let image = image::load_from_memory(&read(&file)?)
+        .map_err(|error| Error::Image(path(&file), error))?
+        .to_rgba8();
+    let dimensions = image.dimensions();
+    let image = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions);
+    Ok(Texture2d::new(display, image)?)
+}
+
+/// A program compiled from `shaders/<name>.*` that is compiled again whenever
+/// one of its sources changes.
+pub struct Shader {
+    vertex: String,
+    name: String,
+    stamps: Vec<Option<SystemTime>>,
+    program: Program,
+}
+
+impl Shader {
+    fn stamps(vertex: &str, name: &str) -> Vec<Option<SystemTime>> {
+        [(vertex, "vert"), (name, "frag"), (name, "geom")]
+            .iter()
+            .map(|(name, extension)| modified(&shader_file(name, extension)))
+            .chain(snippets().map(modified))
+            .collect()
+    }
+
+    pub fn load(display: &glium::Display, name: &str) -> Result<Shader> {
+        Shader::with_vertex(display, name, name)
+    }
+
+    /// Loads `shaders/<name>.frag` behind `shaders/instance.vert`, which places
+    /// and tints every instance of a quad drawn with `utils::Instance`.
+    pub fn instanced(display: &glium::Display, name: &str) -> Result<Shader> {
+        Shader::with_vertex(display, "instance", name)
+    }
+
+    fn with_vertex(display: &glium::Display, vertex: &str, name: &str) -> Result<Shader> {
+        Ok(Shader {
+            vertex: vertex.to_string(),
+            name: name.to_string(),
+            stamps: Shader::stamps(vertex, name),
+            program: program(display, vertex, name)?,
+        })
+    }
+
+    /// Recompiles the program if its sources changed since the last call. When
+    /// they no longer compile the error is reported and the program in use kept.
+    pub fn reload(&mut self, display: &glium::Display) {
+        let stamps = Shader::stamps(&self.vertex, &self.name);
+        if stamps == self.stamps {
+            return;
+        }
+        self.stamps = stamps;
+        match program(display, &self.vertex, &self.name) {
+            Ok(program) => {
+                self.program = program;
+                println!("Reloaded {}", path(&shader_file(&self.name, "*")).display());
+            }
+            Err(error) => eprintln!("Keeping the previous {} shaders:\n{}", self.name, error),
+        }
+    }
+}
+
+impl Deref for Shader {
+    type Target = Program;
+
+    fn deref(&self) -> &Program {
+        &self.program
+    }
+}
+
+/// A texture loaded from `assets/<name>` that is loaded again whenever the
+/// image changes.
+pub struct Texture {
+    name: String,
+    stamp: Option<SystemTime>,
+    texture: Texture2d,
+}
+
+impl Texture {
+    pub fn load(display: &glium::Display, name: &str) -> Result<Texture> {
+        Ok(Texture {
+            name: name.to_string(),
+            stamp: modified(&texture_file(name)),
+            texture: texture(display, name)?,
+        })
+    }
+
+    /// Loads the image again if it changed since the last call, keeping the
+    /// texture in use if it cannot be read. Returns whether it was replaced.
+    pub fn reload(&mut self, display: &glium::Display) -> bool {
+        let stamp = modified(&texture_file(&self.name));
+        if stamp == self.stamp {
+            return false;
+        }
+        self.stamp = stamp;
+        match texture(display, &self.name) {
+            Ok(texture) => {
+                self.texture = texture;
+                println!("Reloaded {}", path(&texture_file(&self.name)).display());
+                true
+            }
+            Err(error) => {
+                eprintln!("Keeping the previous {}: {}", self.name, error);
+                false
+            }
+        }
+    }
+}
+
+impl Deref for Texture {
+    type Target = Texture2d;
+
+    fn deref(&self) -> &Texture2d {
+        &self.texture
+    }
+}
@@ -0,0 +1,45 @@
+use glium::texture::Texture2d;
+
+use super::assets::Texture;
+use super::error::Result;
+
+/// Pixels along each side of a tile in the atlas image.
+pub const TILE_SIZE: u32 = 16;
+
+/// Per-block detail textures packed in a grid, indexed by block type from the
+/// top left. The detail is multiplied with the block colour from the volume, so
+/// mid-grey leaves the colour untouched.
+pub struct Atlas {
+    texture: Texture,
+    tiles: (u32, u32),
+}
+
+impl Atlas {
+    pub fn new(display: &glium::Display) -> Result<Atlas> {
+        let texture = Texture::load(display, "atlas.png")?;
+        let tiles = (texture.width() / TILE_SIZE, texture.height() / TILE_SIZE);
+
+        Ok(Atlas { texture, tiles })
+    }
+
+    /// Picks up changes to the atlas image, which may have gained tiles.
+    pub fn reload(&mut self, display: &glium::Display) {
+        if self.texture.reload(display) {
+            self.tiles = (
+                self.texture.width() / TILE_SIZE,
+                self.texture.height() / TILE_SIZE,
+            );
+        }
+    }
+
+    /// Number of tile columns and rows in the atlas.
+    pub fn tiles(&self) -> [f32; 2] {
+        [self.tiles.0 as f32, self.tiles.1 as f32]
+    }
+
+    pub fn sampler(&self) -> glium::uniforms::Sampler<'_, Texture2d> {
+        glium::uniforms::Sampler::new(&*self.texture)
+            .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+    }
+}
@@ -0,0 +1,59 @@
+//! Block types stored in `Terrain::tiles` and their properties.
+
+pub const AIR: u8 = 0;
+pub const DIRT: u8 = 1;
+pub const TORCH: u8 = 2;
+pub const LAVA: u8 = 3;
+pub const GRASS: u8 = 4;
+pub const STONE: u8 = 5;
+/// Locked door placed by a level, removed once opened with its key.
+pub const DOOR: u8 = 6;
+
+/// Highest light level, given to open sky and the brightest emitters.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Whether light can travel through the block.
+pub fn is_transparent(block: u8) -> bool {
+    block == AIR
+}
+
+/// Block light level emitted by the block itself.
+pub fn emission(block: u8) -> u8 {
+    match block {
+        TORCH => 14,
+        LAVA => MAX_LIGHT,
+        _ => 0,
+    }
+}
+
+/// Name of the block as shown to the player.
+pub fn name(block: u8) -> &'static str {
+    match block {
+        AIR => "air",
+        DIRT => "dirt",
+        TORCH => "torch",
+        LAVA => "lava",
+        GRASS => "grass",
+        STONE => "stone",
+        DOOR => "door",
+        _ => "unknown",
+    }
+}
+
+/// Base colour of the block in the volume texture.
+pub fn color(block: u8) -> (u8, u8, u8) {
+    match block {
+        DIRT => (58, 30, 16),
+        TORCH => (255, 200, 90),
+        LAVA => (230, 90, 20),
+        GRASS => (42, 110, 40),
+        STONE => (96, 94, 98),
+        DOOR => (150, 100, 55),
+        _ => (0, 0, 0),
+    }
+}
+
+/// Perceived brightness of a light level, each step dimming by a fifth.
+pub fn brightness(level: u8) -> f32 {
+    0.8f32.powi(MAX_LIGHT as i32 - level as i32)
+}
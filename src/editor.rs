@@ -0,0 +1,264 @@
+//! Editor mode: paints the terrain with brushes from either view. Clicks in
+//! the 3D view place blocks against the face under the mouse, clicks in the
+//! 2D view place them in the tile under the mouse within the slice.
+
+use std::collections::{HashSet, VecDeque};
+
+use glium::glutin::event::VirtualKeyCode;
+use nalgebra::base::{Matrix4, Vector3, Vector4};
+
+use super::block;
+use super::history::History;
+use super::terrain::Terrain;
+
+/// Most tiles a single fill may change, so filling open air stays bounded.
+const FILL_LIMIT: usize = 4096;
+
+/// Largest brush radius.
+const MAX_RADIUS: isize = 4;
+
+/// Block types that can be painted with, selected with the number keys.
+const BLOCKS: [u8; 6] = [
+    block::DIRT,
+    block::TORCH,
+    block::LAVA,
+    block::GRASS,
+    block::STONE,
+    block::DOOR,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Brush {
+    /// The tile clicked.
+    Single,
+    /// Every tile within the radius along each axis.
+    Box,
+    /// Every tile whose centre lies within the radius.
+    Sphere,
+    /// The tiles connected to the one clicked that hold the same block.
+    Fill,
+}
+
+impl Brush {
+    fn next(self) -> Brush {
+        match self {
+            Brush::Single => Brush::Box,
+            Brush::Box => Brush::Sphere,
+            Brush::Sphere => Brush::Fill,
+            Brush::Fill => Brush::Single,
+        }
+    }
+}
+
+pub struct Editor {
+    pub active: bool,
+    pub brush: Brush,
+    pub radius: isize,
+    pub block: u8,
+    /// Solid tile under the mouse in the 3D view and the tile in front of it.
+    hover: Option<([isize; 3], [isize; 3])>,
+}
+
+/// Ray through a point of the 3D view, given in normalized device
+/// coordinates, as its origin on the near plane and its direction.
+pub fn pick_ray(
+    view: &Matrix4<f32>,
+    perspective: &Matrix4<f32>,
+    (x, y): (f32, f32),
+) -> (Vector3<f32>, Vector3<f32>) {
+    let inverse = (perspective * view).try_inverse().unwrap();
+    let unproject = |z: f32| {
+        let point = inverse * Vector4::new(x, y, z, 1.0);
+        point.xyz() / point.w
+    };
+    let near = unproject(-1.0);
+    let far = unproject(1.0);
+    (near, (far - near).normalize())
+}
+
+/// First solid tile along a ray within `distance`, along with the tile the
+/// ray passed through just before it. Steps from one tile boundary to the next.
+pub fn raycast(
+    terrain: &Terrain,
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    distance: f32,
+) -> Option<([isize; 3], [isize; 3])> {
+    let mut tile = [origin.x, origin.y, origin.z].map(|c| c.floor() as isize);
+    let mut step = [0; 3];
+    let mut next = [f32::INFINITY; 3];
+    let mut delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        if direction[axis] != 0.0 {
+            step[axis] = direction[axis].signum() as isize;
+            let boundary = tile[axis] as f32 + if step[axis] > 0 { 1.0 } else { 0.0 };
+            next[axis] = (boundary - origin[axis]) / direction[axis];
+            delta[axis] = 1.0 / direction[axis].abs();
+        }
+    }
+
+    loop {
+        let axis = (0..3).min_by(|&a, &b| next[a].total_cmp(&next[b])).unwrap();
+        if next[axis] > distance {
+            return None;
+        }
+        let previous = tile;
+        tile[axis] += step[axis];
+        next[axis] += delta[axis];
+        if terrain.get(tile[0], tile[1], tile[2]).unwrap_or(block::AIR) != block::AIR {
+            return Some((tile, previous));
+        }
+    }
+}
+
+impl Default for Editor {
+    fn default() -> Editor {
+        Editor {
+            active: false,
+            brush: Brush::Single,
+            radius: 1,
+            block: block::STONE,
+            hover: None,
+        }
+    }
+}
+
+impl Editor {
+    /// Summary of the brush, shown in the window title.
+    pub fn describe(&self) -> String {
+        match self.brush {
+            Brush::Box | Brush::Sphere => format!(
+                "{:?} brush of radius {}, {}",
+                self.brush,
+                self.radius,
+                block::name(self.block)
+            ),
+            _ => format!("{:?} brush, {}", self.brush, block::name(self.block)),
+        }
+    }
+
+    /// Handles a key released in editor mode. Returns whether the brush changed.
+    pub fn key(&mut self, key: VirtualKeyCode) -> bool {
+        let number = match key {
+            VirtualKeyCode::Key1 => Some(0),
+            VirtualKeyCode::Key2 => Some(1),
+            VirtualKeyCode::Key3 => Some(2),
+            VirtualKeyCode::Key4 => Some(3),
+            VirtualKeyCode::Key5 => Some(4),
+            VirtualKeyCode::Key6 => Some(5),
+            _ => None,
+        };
+        if let Some(number) = number {
+            self.block = BLOCKS[number];
+            return true;
+        }
+        match key {
+            VirtualKeyCode::B => self.brush = self.brush.next(),
+            VirtualKeyCode::LBracket => self.radius = (self.radius - 1).max(1),
+            VirtualKeyCode::RBracket => self.radius = (self.radius + 1).min(MAX_RADIUS),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Looks for the tile under the mouse along a ray from the 3D view.
+    pub fn hover(&mut self, terrain: &Terrain, ray: Option<(Vector3<f32>, Vector3<f32>)>) {
+        let distance = terrain.size() as f32 * 4.0;
+        self.hover =
+            ray.and_then(|(origin, direction)| raycast(terrain, origin, direction, distance));
+    }
+
+    /// Corners of the box around the tiles the brush would paint in the 3D view.
+    pub fn cursor(&self, erase: bool) -> Option<([f32; 3], [f32; 3])> {
+        let (hit, front) = self.hover?;
+        let center = if erase { hit } else { front };
+        let reach = match self.brush {
+            Brush::Box | Brush::Sphere => self.radius,
+            _ => 0,
+        };
+        Some((
+            center.map(|c| (c - reach) as f32),
+            center.map(|c| (c + reach + 1) as f32),
+        ))
+    }
+
+    /// Paints from the 3D view: the selected block goes in front of the tile
+    /// under the mouse, erasing clears the tile itself. Returns the block and
+    /// the tiles painted with it, if the mouse was over a tile.
+    pub fn click(
+        &self,
+        terrain: &mut Terrain,
+        history: &mut History,
+        erase: bool,
+    ) -> Option<(u8, Vec<[isize; 3]>)> {
+        let (hit, front) = self.hover?;
+        let (center, value) = if erase {
+            (hit, block::AIR)
+        } else {
+            (front, self.block)
+        };
+        Some((value, self.paint(terrain, history, center, value)))
+    }
+
+    /// Tiles the brush covers around `center`.
+    fn tiles(&self, terrain: &Terrain, center: [isize; 3]) -> Vec<[isize; 3]> {
+        let r = self.radius;
+        let cube = || {
+            (-r..=r).flat_map(move |dz| {
+                (-r..=r).flat_map(move |dy| (-r..=r).map(move |dx| [dx, dy, dz]))
+            })
+        };
+        let offset = |[dx, dy, dz]: [isize; 3]| [center[0] + dx, center[1] + dy, center[2] + dz];
+        match self.brush {
+            Brush::Single => vec![center],
+            Brush::Box => cube().map(offset).collect(),
+            Brush::Sphere => cube()
+                .filter(|d| d.iter().map(|c| c * c).sum::<isize>() <= r * r)
+                .map(offset)
+                .collect(),
+            Brush::Fill => {
+                let target = match terrain.get(center[0], center[1], center[2]) {
+                    Some(target) => target,
+                    None => return Vec::new(),
+                };
+                let mut tiles = Vec::new();
+                let mut seen = HashSet::from([center]);
+                let mut queue = VecDeque::from([center]);
+                while let Some(tile) = queue.pop_front() {
+                    tiles.push(tile);
+                    if tiles.len() >= FILL_LIMIT {
+                        break;
+                    }
+                    for (axis, sign) in [(0, -1), (0, 1), (1, -1), (1, 1), (2, -1), (2, 1)] {
+                        let mut neighbour = tile;
+                        neighbour[axis] += sign;
+                        if terrain.get(neighbour[0], neighbour[1], neighbour[2]) == Some(target)
+                            && seen.insert(neighbour)
+                        {
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+                tiles
+            }
+        }
+    }
+
+    /// Paints the brush around `center` with `value` as a single undo step,
+    /// returning the tiles the brush covered.
+    pub fn paint(
+        &self,
+        terrain: &mut Terrain,
+        history: &mut History,
+        center: [isize; 3],
+        value: u8,
+    ) -> Vec<[isize; 3]> {
+        let tiles = self.tiles(terrain, center);
+        history.begin();
+        for &tile in &tiles {
+            history.set(terrain, tile, value);
+        }
+        history.commit();
+        tiles
+    }
+}
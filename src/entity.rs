@@ -0,0 +1,313 @@
+//! Dynamic objects other than the player, such as items and projectiles. Every
+//! entity lives in world space; its optional components decide how it moves.
+
+use glium::{implement_vertex, uniform, IndexBuffer, Surface, VertexBuffer};
+use nalgebra::base::{Matrix4, Vector3, Vector4};
+
+use super::assets::Shader;
+use super::error::Result;
+use super::plane;
+use super::sun::Sun;
+use super::terrain::Terrain;
+
+/// Downward acceleration of bodies with gravity, the same the player falls with.
+const GRAVITY: f32 = 30.0;
+
+/// Segments of the circle a ball is cut into by the plane.
+const CIRCLE_SEGMENTS: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Appearance {
+    /// A box reaching `extent` from the position along every axis.
+    Cube([f32; 3]),
+    /// A ball of radius `extent`, drawn as a sprite facing the camera in 3D.
+    Sprite([f32; 3]),
+}
+
+/// Physics component: entities with a body move and collide with the terrain.
+#[derive(Clone, Copy)]
+pub struct Body {
+    pub velocity: Vector3<f32>,
+    pub gravity: bool,
+    /// Fraction of the speed kept when bouncing off a tile.
+    pub bounce: f32,
+}
+
+/// Seconds a ball bounces around before it disappears.
+pub const BALL_LIFETIME: f32 = 3.0;
+
+#[derive(Clone)]
+pub struct Entity {
+    pub position: Vector3<f32>,
+    pub extent: f32,
+    pub appearance: Appearance,
+    pub body: Option<Body>,
+    /// Seconds left before the entity disappears, if it is temporary.
+    pub lifetime: Option<f32>,
+}
+
+impl Entity {
+    fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let p = self.position;
+        let e = self.extent;
+        ([p.x - e, p.y - e, p.z - e], [p.x + e, p.y + e, p.z + e])
+    }
+
+    /// Whether the entity overlaps a solid tile.
+    fn collides(&self, terrain: &Terrain) -> bool {
+        let (min, max) = self.bounds();
+        let min = min.map(|c| c.floor() as isize);
+        let max = max.map(|c| (c - 1e-4).floor() as isize);
+        (min[0]..=max[0]).any(|x| {
+            (min[1]..=max[1])
+                .any(|y| (min[2]..=max[2]).any(|z| terrain.get(x, y, z).unwrap_or(0) != 0))
+        })
+    }
+
+    /// Moves the body one axis at a time, bouncing off any tile it runs into.
+    fn step(&mut self, delta_time: f32, terrain: &Terrain) {
+        let mut body = match self.body {
+            Some(body) => body,
+            None => return,
+        };
+        if body.gravity {
+            body.velocity.y -= GRAVITY * delta_time;
+        }
+
+        for axis in 0..3 {
+            let delta = body.velocity[axis] * delta_time;
+            self.position[axis] += delta;
+            if self.collides(terrain) {
+                self.position[axis] -= delta;
+                body.velocity[axis] *= -body.bounce;
+            }
+        }
+        self.body = Some(body);
+    }
+}
+
+/// The entities of the world, in no particular order.
+#[derive(Default)]
+pub struct Entities {
+    list: Vec<Entity>,
+}
+
+impl Entities {
+    pub fn spawn(&mut self, entity: Entity) {
+        self.list.push(entity);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.list.iter()
+    }
+
+    /// Advances every body and removes entities that expired or fell out of the world.
+    pub fn update(&mut self, delta_time: f32, terrain: &Terrain) {
+        for entity in self.list.iter_mut() {
+            entity.step(delta_time, terrain);
+            if let Some(lifetime) = &mut entity.lifetime {
+                *lifetime -= delta_time;
+            }
+        }
+        let floor = -(terrain.size() as f32);
+        self.list.retain(|entity| {
+            entity.lifetime.is_none_or(|lifetime| lifetime > 0.0) && entity.position.y > floor
+        });
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CubeVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+implement_vertex!(CubeVertex, position, normal);
+
+#[derive(Clone, Copy)]
+struct SectionVertex {
+    position: [f32; 2],
+    color: [f32; 3],
+}
+
+implement_vertex!(SectionVertex, position, color);
+
+/// Unit cube from -1 to 1, two triangles per face.
+fn cube() -> Vec<CubeVertex> {
+    let mut vertices = Vec::new();
+    for axis in 0..3 {
+        for sign in [-1.0, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = sign;
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let corner = |a: f32, b: f32| {
+                let mut position = normal;
+                position[u] = a;
+                position[v] = b;
+                CubeVertex { position, normal }
+            };
+            vertices.extend([
+                corner(-1.0, -1.0),
+                corner(1.0, -1.0),
+                corner(1.0, 1.0),
+                corner(-1.0, -1.0),
+                corner(1.0, 1.0),
+                corner(-1.0, 1.0),
+            ]);
+        }
+    }
+    vertices
+}
+
+pub struct EntityView {
+    cube: VertexBuffer<CubeVertex>,
+    cube_program: Shader,
+    quad: VertexBuffer<super::utils::Vertex>,
+    quad_indices: IndexBuffer<u8>,
+    sprite_program: Shader,
+    section_program: Shader,
+}
+
+impl EntityView {
+    pub fn new(display: &glium::Display) -> Result<EntityView> {
+        let (quad, quad_indices) = super::utils::make_quad(display)?;
+        Ok(EntityView {
+            cube: VertexBuffer::new(display, &cube())?,
+            cube_program: Shader::load(display, "body")?,
+            quad,
+            quad_indices,
+            sprite_program: Shader::load(display, "billboard")?,
+            section_program: Shader::load(display, "section")?,
+        })
+    }
+
+    pub fn reload(&mut self, display: &glium::Display) {
+        self.cube_program.reload(display);
+        self.sprite_program.reload(display);
+        self.section_program.reload(display);
+    }
+
+    /// Draws every entity in the 3D view.
+    pub fn draw(
+        &self,
+        target: &mut glium::Frame,
+        params: &glium::DrawParameters,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+        entities: &Entities,
+        sun: &Sun,
+    ) {
+        let sun_direction: [f32; 3] = sun.direction.into();
+        let sun_color: [f32; 3] = sun.color.into();
+        for entity in entities.iter() {
+            let center: [f32; 3] = entity.position.into();
+            match entity.appearance {
+                Appearance::Cube(color) => {
+                    let model: [[f32; 4]; 4] = (Matrix4::new_translation(&entity.position)
+                        * Matrix4::new_scaling(entity.extent))
+                    .into();
+                    target
+                        .draw(
+                            &self.cube,
+                            glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                            &self.cube_program,
+                            &uniform! {
+                                perspective: perspective,
+                                view: view,
+                                model: model,
+                                color: color,
+                                sun_direction: sun_direction,
+                                sun_color: sun_color,
+                            },
+                            params,
+                        )
+                        .unwrap();
+                }
+                Appearance::Sprite(color) => {
+                    target
+                        .draw(
+                            &self.quad,
+                            &self.quad_indices,
+                            &self.sprite_program,
+                            &uniform! {
+                                perspective: perspective,
+                                view: view,
+                                center: center,
+                                size: entity.extent,
+                                color: color,
+                                sun_color: sun_color,
+                            },
+                            params,
+                        )
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    /// Draws the cross-sections of the entities the plane passes through in the
+    /// 2D view: a polygon for every box and a circle for every ball.
+    pub fn draw_section<T>(
+        &self,
+        display: &glium::Display,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        projection: [[f32; 4]; 4],
+        entities: &Entities,
+        transform: &Matrix4<f32>,
+    ) where
+        T: Surface,
+    {
+        let inverse = transform.try_inverse().unwrap();
+        let mut vertices = Vec::new();
+        for entity in entities.iter() {
+            let (points, color) = match entity.appearance {
+                Appearance::Cube(color) => {
+                    let (min, max) = entity.bounds();
+                    match plane::slice_box(transform, min, max) {
+                        Some(points) => (points, color),
+                        None => continue,
+                    }
+                }
+                Appearance::Sprite(color) => {
+                    let p = entity.position;
+                    let local = inverse * Vector4::new(p.x, p.y, p.z, 1.0);
+                    if local.z.abs() >= entity.extent {
+                        continue;
+                    }
+                    let radius = (entity.extent.powi(2) - local.z.powi(2)).sqrt();
+                    let points = (0..CIRCLE_SEGMENTS)
+                        .map(|i| {
+                            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                            [
+                                local.x + radius * angle.cos(),
+                                local.y + radius * angle.sin(),
+                            ]
+                        })
+                        .collect();
+                    (points, color)
+                }
+            };
+
+            for k in 1..points.len() - 1 {
+                for position in [points[0], points[k], points[k + 1]] {
+                    vertices.push(SectionVertex { position, color });
+                }
+            }
+        }
+
+        if vertices.is_empty() {
+            return;
+        }
+        let buffer = VertexBuffer::new(display, &vertices).unwrap();
+        target
+            .draw(
+                &buffer,
+                glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                &self.section_program,
+                &uniform! { projection: projection },
+                params,
+            )
+            .unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+//! Everything that can keep the game from starting, with enough context to fix it.
+
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be read.
+    Io(PathBuf, std::io::Error),
+    /// An image could not be decoded.
+    Image(PathBuf, image::ImageError),
+    /// A shader failed to compile or link. Holds the driver's log, rewritten
+    /// to point at the source files.
+    Shader(String),
+    /// The window or its OpenGL context could not be created.
+    Display(glium::backend::glutin::DisplayCreationError),
+    /// The OpenGL implementation lacks something the game needs.
+    Unsupported(String),
+    /// A buffer or texture could not be created.
+    Gpu(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            Error::Image(path, error) => write!(f, "{}: {}", path.display(), error),
+            Error::Shader(log) => write!(f, "{}", log),
+            Error::Display(error) => write!(f, "could not create the window: {}", error),
+            Error::Unsupported(message) => write!(f, "{}", message),
+            Error::Gpu(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<glium::backend::glutin::DisplayCreationError> for Error {
+    fn from(error: glium::backend::glutin::DisplayCreationError) -> Error {
+        Error::Display(error)
+    }
+}
+
+impl From<glium::vertex::BufferCreationError> for Error {
+    fn from(error: glium::vertex::BufferCreationError) -> Error {
+        Error::Gpu(format!("could not create a vertex buffer: {}", error))
+    }
+}
+
+impl From<glium::index::BufferCreationError> for Error {
+    fn from(error: glium::index::BufferCreationError) -> Error {
+        Error::Gpu(format!("could not create an index buffer: {}", error))
+    }
+}
+
+impl From<glium::texture::TextureCreationError> for Error {
+    fn from(error: glium::texture::TextureCreationError) -> Error {
+        Error::Gpu(format!("could not create a texture: {}", error))
+    }
+}
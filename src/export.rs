@@ -0,0 +1,293 @@
+//! Writes the current cross-section to SVG and PNG files, with the plane
+//! recorded alongside so the same slice can be exported again later.
+
+use std::io::Write;
+use std::path::Path;
+
+use nalgebra::base::{Matrix4, Vector4};
+
+use super::block;
+use super::plane;
+use super::smooth::{self, Style};
+use super::terrain::Terrain;
+
+/// Pixels along each tile in the rasterised image.
+const PIXELS_PER_TILE: usize = 32;
+
+/// Background of the exported images, the same sky the window is cleared to.
+const SKY: (u8, u8, u8) = (121, 183, 226);
+
+/// A filled region of the slice in plane coordinates, with the block it shows.
+pub struct SlicePolygon {
+    pub points: Vec<[f32; 2]>,
+    pub tile: u8,
+}
+
+/// Cuts the terrain with the plane of the given transform. Blocky terrain is
+/// sliced tile by tile, smooth terrain uses the contour shown on screen.
+pub fn slice_polygons(terrain: &Terrain, transform: &Matrix4<f32>) -> Vec<SlicePolygon> {
+    if terrain.style() == Style::Smooth {
+        return smooth::contour_polygons(terrain, transform)
+            .into_iter()
+            .map(|(points, _, tile)| SlicePolygon { points, tile })
+            .collect();
+    }
+
+    let size = terrain.size();
+    let half = size as f32 / 2.0;
+    let offsets = terrain.periodic_offsets(&plane::corners(transform, size));
+    let mut polygons = Vec::new();
+    for offset in offsets {
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let tile = terrain.get_unsafe(x, y, z);
+                    if tile == block::AIR {
+                        continue;
+                    }
+
+                    let min = [
+                        x as f32 + offset[0],
+                        y as f32 + offset[1],
+                        z as f32 + offset[2],
+                    ];
+                    let points = match plane::slice_box(transform, min, min.map(|c| c + 1.0)) {
+                        Some(points) => points,
+                        None => continue,
+                    };
+                    let outside =
+                        |axis: usize, sign: f32| points.iter().all(|p| p[axis] * sign > half);
+                    if outside(0, 1.0) || outside(0, -1.0) || outside(1, 1.0) || outside(1, -1.0) {
+                        continue;
+                    }
+                    polygons.push(SlicePolygon { points, tile });
+                }
+            }
+        }
+    }
+
+    polygons
+}
+
+/// Origin of the plane and its rotation about the Y axis in degrees.
+fn pose(transform: &Matrix4<f32>) -> (Vector4<f32>, f32) {
+    let origin = transform * Vector4::new(0.0, 0.0, 0.0, 1.0);
+    let yaw = transform[(0, 2)].atan2(transform[(0, 0)]).to_degrees();
+    (origin, yaw)
+}
+
+/// Quotes a word for the shell where it needs it.
+fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_.,/:=+".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Command line that exports the same slice again, given the options the
+/// terrain was created with.
+fn command(world: &[String], transform: &Matrix4<f32>, path: &Path) -> String {
+    let (origin, yaw) = pose(transform);
+    let mut command = format!("xsection export-slice {}", quote(&path.to_string_lossy()));
+    for option in world {
+        command.push(' ');
+        command.push_str(&quote(option));
+    }
+    command.push_str(&format!(
+        " --origin {},{},{} --yaw {}",
+        origin.x, origin.y, origin.z, yaw
+    ));
+    command
+}
+
+/// Escapes text for use in XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the slice as an SVG in plane units, Y pointing up like on screen.
+pub fn write_svg(
+    terrain: &Terrain,
+    transform: &Matrix4<f32>,
+    world: &[String],
+    path: &Path,
+) -> std::io::Result<()> {
+    let size = terrain.size() as f32;
+    let half = size / 2.0;
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    writeln!(
+        file,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        -half, -half, size, size
+    )?;
+    writeln!(
+        file,
+        "  <desc>{}</desc>",
+        escape(&command(world, transform, path))
+    )?;
+    let (origin, yaw) = pose(transform);
+    writeln!(
+        file,
+        r#"  <metadata><plane xmlns="urn:xsection" origin="{},{},{}" yaw="{}" size="{}" wrap="{}" style="{}"/></metadata>"#,
+        origin.x,
+        origin.y,
+        origin.z,
+        yaw,
+        terrain.size(),
+        terrain.wrap(),
+        match terrain.style() {
+            Style::Blocky => "blocky",
+            Style::Smooth => "smooth",
+        }
+    )?;
+    writeln!(
+        file,
+        r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="rgb{:?}"/>"#,
+        -half, -half, size, size, SKY
+    )?;
+
+    for polygon in slice_polygons(terrain, transform) {
+        let points: Vec<String> = polygon
+            .points
+            .iter()
+            .map(|p| format!("{},{}", p[0], -p[1]))
+            .collect();
+        writeln!(
+            file,
+            r#"  <polygon points="{}" fill="rgb{:?}"/>"#,
+            points.join(" "),
+            block::color(polygon.tile)
+        )?;
+    }
+
+    writeln!(file, "</svg>")?;
+    file.flush()
+}
+
+/// Whether a point lies inside a polygon, by counting the edges a ray crosses.
+fn contains(points: &[[f32; 2]], p: [f32; 2]) -> bool {
+    let mut inside = false;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// CRC of a PNG chunk, computed over its type and data.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// An `iTXt` chunk holding UTF-8 text under `keyword`, uncompressed.
+fn text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+    let mut body = b"iTXt".to_vec();
+    body.extend_from_slice(keyword.as_bytes());
+    // No compression, and neither a language nor a translated keyword.
+    body.extend_from_slice(&[0, 0, 0, 0, 0]);
+    body.extend_from_slice(text.as_bytes());
+
+    let mut chunk = ((body.len() - 4) as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&crc32(&body).to_be_bytes());
+    chunk
+}
+
+/// Rasterises the slice into a PNG, storing the plane in its text chunks.
+pub fn write_png(
+    terrain: &Terrain,
+    transform: &Matrix4<f32>,
+    world: &[String],
+    path: &Path,
+) -> std::io::Result<()> {
+    let pixels = terrain.size() * PIXELS_PER_TILE;
+    let half = terrain.size() as f32 / 2.0;
+    let scale = PIXELS_PER_TILE as f32;
+    let mut image = image::RgbaImage::from_pixel(
+        pixels as u32,
+        pixels as u32,
+        image::Rgba([SKY.0, SKY.1, SKY.2, 255]),
+    );
+
+    for polygon in slice_polygons(terrain, transform) {
+        // Pixel coordinates with row zero at the top of the plane.
+        let to_pixel = |p: &[f32; 2]| [(p[0] + half) * scale, (half - p[1]) * scale];
+        let corners: Vec<[f32; 2]> = polygon.points.iter().map(to_pixel).collect();
+        let clamp = |c: f32| (c.max(0.0) as usize).min(pixels);
+        let bounds = |axis: usize| {
+            let (min, max) = corners.iter().fold((f32::MAX, f32::MIN), |(min, max), c| {
+                (min.min(c[axis]), max.max(c[axis]))
+            });
+            clamp(min.floor())..clamp(max.ceil())
+        };
+
+        let (r, g, b) = block::color(polygon.tile);
+        for y in bounds(1) {
+            for x in bounds(0) {
+                if contains(&corners, [x as f32 + 0.5, y as f32 + 0.5]) {
+                    image.put_pixel(x as u32, y as u32, image::Rgba([r, g, b, 255]));
+                }
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    image
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(std::io::Error::other)?;
+
+    // The text chunks go right after the header chunk, which follows the
+    // 8 byte signature and takes 25 bytes.
+    let (head, rest) = png.split_at(33);
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    file.write_all(head)?;
+    file.write_all(&text_chunk("Title", "XSection slice"))?;
+    file.write_all(&text_chunk("Comment", &command(world, transform, path)))?;
+    file.write_all(rest)?;
+    file.flush()
+}
+
+/// Exports the slice to `path`, picking the format from its extension. Without
+/// a known extension both an SVG and a PNG are written next to each other.
+/// `world` holds the command line options the terrain was created with, which
+/// the files record so the slice can be exported again.
+pub fn export(
+    terrain: &Terrain,
+    transform: &Matrix4<f32>,
+    world: &[String],
+    path: &Path,
+) -> std::io::Result<()> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("svg") => write_svg(terrain, transform, world, path),
+        Some("png") => write_png(terrain, transform, world, path),
+        _ => {
+            write_svg(terrain, transform, world, &path.with_extension("svg"))?;
+            write_png(terrain, transform, world, &path.with_extension("png"))
+        }
+    }
+}
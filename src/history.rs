@@ -0,0 +1,136 @@
+//! Undo history for terrain edits. Every change of a tile is recorded as an
+//! `Edit` that can be applied or reverted, and edits made together, such as
+//! one stroke of an editor brush, form a transaction undone as a whole.
+
+use std::collections::VecDeque;
+
+use super::terrain::Terrain;
+
+/// Edits kept over all transactions before the oldest ones are forgotten.
+pub const LIMIT: usize = 100_000;
+
+/// A tile changing from one block to another.
+#[derive(Clone, Copy)]
+struct Edit {
+    tile: [isize; 3],
+    before: u8,
+    after: u8,
+}
+
+impl Edit {
+    fn apply(&self, terrain: &mut Terrain) {
+        let [x, y, z] = self.tile;
+        terrain.set(x, y, z, self.after);
+    }
+
+    fn revert(&self, terrain: &mut Terrain) {
+        let [x, y, z] = self.tile;
+        terrain.set(x, y, z, self.before);
+    }
+}
+
+pub struct History {
+    undo: VecDeque<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    /// Transaction being recorded, if one was begun.
+    open: Option<Vec<Edit>>,
+    /// Edits held by `undo`.
+    size: usize,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            size: 0,
+            limit,
+        }
+    }
+
+    /// Starts a transaction: every edit until `commit` is undone in one step.
+    pub fn begin(&mut self) {
+        self.commit();
+        self.open = Some(Vec::new());
+    }
+
+    /// Ends the current transaction, if any.
+    pub fn commit(&mut self) {
+        if let Some(edits) = self.open.take() {
+            self.push(edits);
+        }
+    }
+
+    fn push(&mut self, edits: Vec<Edit>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.size += edits.len();
+        self.undo.push_back(edits);
+        self.redo.clear();
+        // Forget the oldest transactions, but never the newest one.
+        while self.size > self.limit && self.undo.len() > 1 {
+            let oldest = self.undo.pop_front().unwrap();
+            self.size -= oldest.len();
+        }
+    }
+
+    /// Sets a tile and records the change, as part of the open transaction or
+    /// as a transaction of its own. Returns the block the tile held before.
+    pub fn set(&mut self, terrain: &mut Terrain, tile: [isize; 3], value: u8) -> Option<u8> {
+        let before = terrain.set(tile[0], tile[1], tile[2], value)?;
+        if before != value {
+            let edit = Edit {
+                tile,
+                before,
+                after: value,
+            };
+            match &mut self.open {
+                Some(edits) => edits.push(edit),
+                None => self.push(vec![edit]),
+            }
+        }
+        Some(before)
+    }
+
+    /// Reverts the last transaction. Returns whether there was one.
+    pub fn undo(&mut self, terrain: &mut Terrain) -> bool {
+        self.commit();
+        match self.undo.pop_back() {
+            Some(edits) => {
+                self.size -= edits.len();
+                for edit in edits.iter().rev() {
+                    edit.revert(terrain);
+                }
+                self.redo.push(edits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the last undone transaction again. Returns whether there was one.
+    pub fn redo(&mut self, terrain: &mut Terrain) -> bool {
+        match self.redo.pop() {
+            Some(edits) => {
+                for edit in edits.iter() {
+                    edit.apply(terrain);
+                }
+                self.size += edits.len();
+                self.undo.push_back(edits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets everything, for when the terrain was replaced as a whole.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.size = 0;
+    }
+}
@@ -0,0 +1,301 @@
+//! Puzzle levels: goal flags, keys and doors anchored in the world, which only
+//! show up in the 2D view while the plane passes close to them.
+//!
+//! A level file lists one entity or win condition per line:
+//!
+//! ```text
+//! goal <x> <y> <z>
+//! key <colour> <x> <y> <z>
+//! door <colour> <x> <y> <z>
+//! win reach|keys
+//! ```
+//!
+//! Goals and keys sit at world positions, doors fill the tile at integer
+//! coordinates. Everything after a `#` is a comment.
+
+use std::collections::HashSet;
+
+use glium::{uniform, IndexBuffer, Surface, VertexBuffer};
+use nalgebra::base::{Matrix4, Vector3, Vector4};
+
+use super::assets::Shader;
+use super::block;
+use super::terrain::Terrain;
+
+/// Distance from the plane within which entities are seen and can be touched.
+const VISIBLE_DISTANCE: f32 = 0.5;
+
+/// Distance between the player and an entity at which they interact.
+const REACH: f32 = 1.25;
+
+/// Colours keys and doors are matched by.
+const COLORS: [(&str, [f32; 3]); 4] = [
+    ("red", [0.9, 0.15, 0.1]),
+    ("green", [0.2, 0.8, 0.2]),
+    ("blue", [0.2, 0.4, 1.0]),
+    ("yellow", [1.0, 0.85, 0.1]),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Goal,
+    Key(String),
+    Door(String),
+}
+
+pub struct Entity {
+    pub kind: Kind,
+    pub position: Vector3<f32>,
+    /// Cleared once a key is picked up or a door opened.
+    pub active: bool,
+}
+
+/// What has to be done to complete a level. All conditions must hold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    /// Touch a goal flag.
+    Reach,
+    /// Pick up every key.
+    Keys,
+}
+
+pub struct Level {
+    pub entities: Vec<Entity>,
+    conditions: Vec<Condition>,
+    keys: HashSet<String>,
+    reached: bool,
+}
+
+fn color(name: &str) -> Option<[f32; 3]> {
+    COLORS.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+}
+
+/// Position of the point in the plane's own coordinates, Z being the distance
+/// in front of or behind it.
+fn plane_local(transform: &Matrix4<f32>, position: Vector3<f32>) -> Vector3<f32> {
+    let inverse = transform.try_inverse().unwrap();
+    (inverse * Vector4::new(position.x, position.y, position.z, 1.0)).xyz()
+}
+
+impl Level {
+    pub fn parse(source: &str) -> Result<Level, String> {
+        let mut level = Level {
+            entities: Vec::new(),
+            conditions: Vec::new(),
+            keys: HashSet::new(),
+            reached: false,
+        };
+
+        for (number, line) in source.lines().enumerate() {
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let numbers = |words: &[&str]| -> Result<Vector3<f32>, String> {
+                let values = words
+                    .iter()
+                    .map(|w| w.parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(|_| error("expected a number"))?;
+                match values[..] {
+                    [x, y, z] => Ok(Vector3::new(x, y, z)),
+                    _ => Err(error("expected x y z")),
+                }
+            };
+            let colored = |name: &str| match color(name) {
+                Some(_) => Ok(name.to_string()),
+                None => Err(error(&format!("unknown colour {}", name))),
+            };
+
+            let (kind, position) = match words.as_slice() {
+                [] => continue,
+                ["goal", rest @ ..] => (Kind::Goal, numbers(rest)?),
+                ["key", name, rest @ ..] => (Kind::Key(colored(name)?), numbers(rest)?),
+                ["door", name, rest @ ..] => {
+                    // Doors are stored at the centre of their tile.
+                    let tile = numbers(rest)?.map(|c| c.floor() + 0.5);
+                    (Kind::Door(colored(name)?), tile)
+                }
+                ["win", "reach"] => {
+                    level.conditions.push(Condition::Reach);
+                    continue;
+                }
+                ["win", "keys"] => {
+                    level.conditions.push(Condition::Keys);
+                    continue;
+                }
+                _ => return Err(error(&format!("cannot parse '{}'", line.trim()))),
+            };
+            level.entities.push(Entity {
+                kind,
+                position,
+                active: true,
+            });
+        }
+
+        if level.conditions.is_empty() {
+            level.conditions.push(Condition::Reach);
+        }
+        Ok(level)
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Level, String> {
+        let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Level::parse(&source)
+    }
+
+    /// Puts the closed doors into the terrain, for instance again after it
+    /// was replaced by another slice of a 4D world.
+    pub fn place(&self, terrain: &mut Terrain) {
+        for entity in self.entities.iter().filter(|entity| entity.active) {
+            if let Kind::Door(_) = entity.kind {
+                let tile = entity.position.map(|c| c.floor() as isize);
+                terrain.set(tile.x, tile.y, tile.z, block::DOOR);
+            }
+        }
+    }
+
+    /// Whether the entity can currently be seen in the plane with the given transform.
+    pub fn visible(&self, entity: &Entity, transform: &Matrix4<f32>, size: usize) -> bool {
+        let local = plane_local(transform, entity.position);
+        let half = size as f32 / 2.0;
+        entity.active
+            && local.z.abs() < VISIBLE_DISTANCE
+            && local.x.abs() < half
+            && local.y.abs() < half
+    }
+
+    /// Lets the player at `player` touch whatever visible entities are in reach.
+    /// Returns a message for everything that happened.
+    pub fn update(
+        &mut self,
+        terrain: &mut Terrain,
+        transform: &Matrix4<f32>,
+        player: Vector3<f32>,
+    ) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut opened = Vec::new();
+        let was_won = self.won();
+
+        for i in 0..self.entities.len() {
+            let entity = &self.entities[i];
+            if !self.visible(entity, transform, terrain.size())
+                || (entity.position - player).norm() > REACH
+            {
+                continue;
+            }
+
+            let entity = &mut self.entities[i];
+            match &entity.kind {
+                Kind::Goal => {
+                    if !self.reached {
+                        messages.push("Reached the goal".to_string());
+                    }
+                    self.reached = true;
+                }
+                Kind::Key(name) => {
+                    messages.push(format!("Picked up the {} key", name));
+                    self.keys.insert(name.clone());
+                    entity.active = false;
+                }
+                Kind::Door(name) if self.keys.contains(name) && !opened.contains(name) => {
+                    messages.push(format!("Opened the {} door", name));
+                    opened.push(name.clone());
+                }
+                Kind::Door(_) => (),
+            }
+        }
+
+        // A key opens every door of its colour, so tall doors open as a whole.
+        for entity in self.entities.iter_mut() {
+            if let Kind::Door(name) = &entity.kind {
+                if entity.active && opened.contains(name) {
+                    let tile = entity.position.map(|c| c.floor() as isize);
+                    terrain.set(tile.x, tile.y, tile.z, block::AIR);
+                    entity.active = false;
+                }
+            }
+        }
+
+        if self.won() && !was_won {
+            messages.push("Level complete".to_string());
+        }
+        messages
+    }
+
+    pub fn won(&self) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Reach => self.reached,
+            Condition::Keys => self
+                .entities
+                .iter()
+                .all(|entity| !matches!(entity.kind, Kind::Key(_)) || !entity.active),
+        })
+    }
+}
+
+/// Draws the entities of a level that the plane passes close to, fading them
+/// out with their distance to it.
+pub struct LevelView {
+    buffer: VertexBuffer<super::utils::Vertex>,
+    indices: IndexBuffer<u8>,
+    program: Shader,
+}
+
+impl LevelView {
+    pub fn new(display: &glium::Display) -> super::error::Result<LevelView> {
+        let program = Shader::load(display, "entity")?;
+        let (buffer, indices) = super::utils::make_quad(display)?;
+
+        Ok(LevelView {
+            buffer,
+            indices,
+            program,
+        })
+    }
+
+    pub fn reload(&mut self, display: &glium::Display) {
+        self.program.reload(display);
+    }
+
+    pub fn draw<T>(
+        &self,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        projection: [[f32; 4]; 4],
+        level: &Level,
+        transform: &Matrix4<f32>,
+        size: usize,
+    ) where
+        T: Surface,
+    {
+        for entity in level.entities.iter() {
+            if !level.visible(entity, transform, size) {
+                continue;
+            }
+
+            let local = plane_local(transform, entity.position);
+            let (shape, color) = match &entity.kind {
+                Kind::Goal => (0, [1.0, 1.0, 1.0]),
+                Kind::Key(name) => (1, color(name).unwrap()),
+                Kind::Door(name) => (2, color(name).unwrap()),
+            };
+            let alpha = 1.0 - local.z.abs() / VISIBLE_DISTANCE;
+            target
+                .draw(
+                    &self.buffer,
+                    &self.indices,
+                    &self.program,
+                    &uniform! {
+                        projection: projection,
+                        center: [local.x, local.y],
+                        size: 0.5f32,
+                        color: color,
+                        alpha: alpha,
+                        shape: shape,
+                    },
+                    params,
+                )
+                .unwrap();
+        }
+    }
+}
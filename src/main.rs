@@ -1,20 +1,347 @@
+mod assets;
+mod atlas;
+mod block;
+mod editor;
+mod entity;
+mod error;
+mod export;
+mod history;
+mod level;
+mod mesh;
+mod network;
+mod overlay;
 mod plane;
 mod player;
+mod replay;
+mod script;
+mod slice;
+mod smooth;
+mod sun;
 mod terrain;
 mod utils;
+mod views;
+mod volume;
+mod vox;
+mod world;
+mod world4d;
 mod xsection;
 
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use glium::{glutin, Surface};
 use glutin::event::VirtualKeyCode;
-use nalgebra::base::{Matrix, Matrix4, Vector3};
+use nalgebra::base::{Matrix, Matrix4, Vector3, Vector4};
 use nalgebra::geometry::Point3;
 
-use plane::Plane;
-use player::Player;
-use terrain::Terrain;
+use atlas::Atlas;
+use editor::Editor;
+use entity::{Appearance, Body, Entities, Entity, EntityView};
+use history::History;
+use level::{Level, LevelView};
+use network::{Client, Server};
+use overlay::{Overlay, Stats};
+use plane::PlaneView;
+use player::{Player, PlayerView};
+use replay::{Recorder, State, Tick};
+use script::{Event, Script};
+use smooth::{Contour, Style};
+use sun::Sun;
+use terrain::{Terrain, TerrainView};
+use utils::Instance;
+use views::Views;
+use volume::Volume;
+use world4d::{Hyperplane, Terrain4};
 use xsection::XSection;
 
+/// Value following `name` among `args`, if present.
+fn option_value(args: &[String], name: &str) -> Option<String> {
+    let mut args = args.iter().skip_while(|arg| *arg != name);
+    args.next();
+    args.next().cloned()
+}
+
+/// Value following `name` on the command line, if present.
+fn arg_value(name: &str) -> Option<String> {
+    option_value(&std::env::args().collect::<Vec<_>>(), name)
+}
+
+/// The options on the command line that shape the world, which a recording
+/// keeps so it plays back in the same world.
+fn world_options() -> Vec<String> {
+    let mut options = Vec::new();
+    for flag in ["--wrap", "--smooth", "--cave", "--4d"] {
+        if std::env::args().any(|arg| arg == flag) {
+            options.push(flag.to_string());
+        }
+    }
+    for name in ["--world", "--vox", "--level", "--views", "--hyperplane"] {
+        if let Some(value) = arg_value(name) {
+            options.extend([name.to_string(), value]);
+        }
+    }
+    options
+}
+
+/// Parses a comma separated `x,y,z` triple from the command line.
+fn arg_vector(name: &str) -> Option<Vector3<f32>> {
+    let value = arg_value(name)?;
+    let components = value
+        .split(',')
+        .map(|c| c.trim().parse::<f32>().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    if components.len() == 3 {
+        Some(Vector3::new(components[0], components[1], components[2]))
+    } else {
+        None
+    }
+}
+
+/// Terrain configured by the `--wrap` and `--smooth` flags, loaded from the
+/// world file given with `--world` or the model given with `--vox`, and
+/// generated otherwise, with a lit cave given `--cave`.
+fn create_terrain(options: &[String]) -> Terrain {
+    let wrap = options.iter().any(|arg| arg == "--wrap");
+    let style = if options.iter().any(|arg| arg == "--smooth") {
+        Style::Smooth
+    } else {
+        Style::Blocky
+    };
+    if let Some(path) = option_value(options, "--world") {
+        return world::load(std::path::Path::new(&path), wrap, style).unwrap_or_else(|error| {
+            eprintln!("Could not load {}: {}", path, error);
+            std::process::exit(1);
+        });
+    }
+    match option_value(options, "--vox") {
+        Some(path) => match vox::load(std::path::Path::new(&path)) {
+            Ok(model) => model.to_terrain(wrap, style),
+            Err(error) => {
+                eprintln!("Could not load {}: {}", path, error);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let mut terrain = Terrain::new(16, wrap, style);
+            if options.iter().any(|arg| arg == "--cave") {
+                terrain.carve_cave();
+            }
+            terrain
+        }
+    }
+}
+
+/// Stops when `--4d`, which slices a generated world of its own, is given
+/// with a world to load, which it would replace along with its colours.
+fn reject_4d_files(options: &[String]) {
+    if option_value(options, "--world").is_some() || option_value(options, "--vox").is_some() {
+        eprintln!("--4d slices a world of its own and cannot be combined with --world or --vox");
+        std::process::exit(1);
+    }
+}
+
+/// Pose of the hyperplane given with `--hyperplane`, or where it starts out.
+fn create_hyperplane(options: &[String], size: usize) -> Hyperplane {
+    option_value(options, "--hyperplane")
+        .and_then(|value| Hyperplane::parse(&value))
+        .unwrap_or_else(|| Hyperplane::new(size))
+}
+
+/// Options an exported slice records to be exported again: those that shape
+/// the world, and in 4D mode the pose of the hyperplane.
+fn export_options(options: &[String], hyperplane: Option<&Hyperplane>) -> Vec<String> {
+    let mut export = Vec::new();
+    let mut words = options.iter();
+    while let Some(word) = words.next() {
+        // Views do not change what a slice shows, and the hyperplane has
+        // moved on since the start.
+        if word == "--views" || word == "--hyperplane" {
+            words.next();
+        } else if word != "--4d" {
+            export.push(word.clone());
+        }
+    }
+    if let Some(hyperplane) = hyperplane {
+        export.extend(["--4d".to_string(), "--hyperplane".to_string(), hyperplane.describe()]);
+    }
+    export
+}
+
+/// `xsection export-slice <path> [--origin x,y,z] [--yaw degrees]` writes a
+/// slice of the terrain without opening a window.
+fn export_slice(path: &str) {
+    let options = world_options();
+    let four_d = options.iter().any(|option| option == "--4d");
+    if four_d {
+        reject_4d_files(&options);
+    }
+    let mut terrain = create_terrain(&options);
+    let hyperplane = four_d.then(|| create_hyperplane(&options, terrain.size()));
+    if let Some(hyperplane) = &hyperplane {
+        terrain.load(Terrain4::new(terrain.size()).slice(hyperplane));
+    }
+    if let Some(level) = load_level(&options) {
+        level.place(&mut terrain);
+    }
+    let initial = plane::initial_transform(terrain.size());
+    let origin = arg_vector("--origin").unwrap_or_else(|| initial.column(3).xyz());
+    let yaw = match arg_value("--yaw").and_then(|yaw| yaw.parse::<f32>().ok()) {
+        Some(yaw) => yaw.to_radians(),
+        None => initial[(0, 2)].atan2(initial[(0, 0)]),
+    };
+
+    let transform = plane::pose(origin, yaw);
+    let world = export_options(&options, hyperplane.as_ref());
+    if let Err(error) = export::export(&terrain, &transform, &world, std::path::Path::new(path)) {
+        eprintln!("Could not export slice to {}: {}", path, error);
+        std::process::exit(1);
+    }
+}
+
+/// `xsection export-mesh <path>` writes the 3D terrain mesh without opening a window.
+fn export_mesh(path: &str) {
+    let mut terrain = create_terrain(&world_options());
+    terrain.rebuild();
+    if let Err(error) = mesh::export(&terrain, std::path::Path::new(path)) {
+        eprintln!("Could not export mesh to {}: {}", path, error);
+        std::process::exit(1);
+    }
+}
+
+/// Names of the slice views given with `--views`, or a single free view.
+fn view_names(options: &[String]) -> Vec<String> {
+    let names: Vec<String> = option_value(options, "--views")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        vec!["free".to_string()]
+    } else {
+        names
+    }
+}
+
+/// Puzzle level given with `--level`, if any.
+fn load_level(options: &[String]) -> Option<Level> {
+    option_value(options, "--level").map(|path| {
+        Level::load(std::path::Path::new(&path)).unwrap_or_else(|error| {
+            eprintln!("Could not load level {}: {}", path, error);
+            std::process::exit(1);
+        })
+    })
+}
+
+/// `xsection --replay <path>` plays a recording back without a window and
+/// prints where the plane and the player ended up, failing when that is not
+/// where the recorded game ended.
+fn replay(path: &str) {
+    let replay = replay::load(std::path::Path::new(path)).unwrap_or_else(|error| {
+        eprintln!("Could not load replay {}: {}", path, error);
+        std::process::exit(1);
+    });
+    let options = &replay.world;
+    let four_d = options.iter().any(|option| option == "--4d");
+    if four_d {
+        reject_4d_files(options);
+    }
+    let mut terrain = create_terrain(options);
+    let world4d = four_d.then(|| {
+        let size = terrain.size();
+        (Terrain4::new(size), create_hyperplane(options, size))
+    });
+    let level = load_level(options);
+    let state = replay::play(&replay, &mut terrain, world4d, level, &view_names(options));
+    println!("Replayed {} ticks, ending in", replay.ticks.len());
+    println!("{}", state);
+    match replay.end {
+        Some(end) if end != state => {
+            eprintln!("The recorded game ended in\n{}", end);
+            std::process::exit(1);
+        }
+        Some(_) => println!("This matches the recorded game"),
+        None => println!("The recording has no end state to compare with"),
+    }
+}
+
+/// `xsection server [--port n]` hosts the world for players joining with
+/// `--connect`, without opening a window.
+fn serve() {
+    let mut options = world_options();
+    if let Some(index) = options.iter().position(|option| option == "--level") {
+        eprintln!("Levels are not played on the server");
+        options.drain(index..index + 2);
+    }
+    // Every client picks its own views.
+    if let Some(index) = options.iter().position(|option| option == "--views") {
+        options.drain(index..index + 2);
+    }
+    let port = arg_value("--port")
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(network::PORT);
+    let terrain = create_terrain(&options);
+    let mut server = Server::bind(("0.0.0.0", port), terrain, options).unwrap_or_else(|error| {
+        eprintln!("Could not listen on port {}: {}", port, error);
+        std::process::exit(1);
+    });
+    match server.local_addr() {
+        Ok(address) => println!("Serving on {}", address),
+        Err(_) => println!("Serving on port {}", port),
+    }
+    if let Err(error) = server.run() {
+        eprintln!("The server stopped: {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Seconds since the epoch, to give every export from the window its own name.
+fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
 fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("export-slice") => {
+            match std::env::args().nth(2) {
+                Some(path) => export_slice(&path),
+                None => eprintln!("Usage: xsection export-slice <path> [--origin x,y,z] [--yaw degrees]"),
+            }
+            return;
+        }
+        Some("export-mesh") => {
+            match std::env::args().nth(2) {
+                Some(path) => export_mesh(&path),
+                None => eprintln!("Usage: xsection export-mesh <path>"),
+            }
+            return;
+        }
+        Some("server") => {
+            serve();
+            return;
+        }
+        _ => (),
+    }
+    if let Some(path) = arg_value("--replay") {
+        replay(&path);
+        return;
+    }
+
+    if let Err(error) = run() {
+        eprintln!("Could not start XSection:\n{}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Opens the window and runs the game until it is closed.
+fn run() -> error::Result<()> {
+    if let Some(directory) = arg_value("--assets") {
+        assets::set_directory(directory.into());
+    }
+
     let width = 1440.0;
     let height = 720.0;
     let event_loop = glium::glutin::event_loop::EventLoop::new();
@@ -22,16 +349,75 @@ fn main() {
         .with_inner_size(glium::glutin::dpi::LogicalSize::new(width, height))
         .with_title("XSection");
     let cb = glium::glutin::ContextBuilder::new().with_depth_buffer(24);
-    let display = glium::Display::new(wb, cb, &event_loop).unwrap();
+    let display = glium::Display::new(wb, cb, &event_loop)?;
+    let required = glium::Version(glium::Api::Gl, 3, 3);
+    if !display.is_glsl_version_supported(&required) {
+        return Err(error::Error::Unsupported(format!(
+            "GLSL 3.30 is required, but the driver only offers OpenGL {}",
+            display.get_opengl_version_string()
+        )));
+    }
 
-    let mut terrain = Terrain::new(&display, 16);
-    terrain.rebuild(&display);
+    // A shared world comes from the server, with the options it was made with.
+    let connection = arg_value("--connect").map(|address| {
+        Client::connect(&address).unwrap_or_else(|error| {
+            eprintln!("Could not join {}: {}", address, error);
+            std::process::exit(1);
+        })
+    });
+    let (mut client, options, mut terrain) = match connection {
+        Some((client, terrain)) => {
+            let options = client.options.clone();
+            (Some(client), options, terrain)
+        }
+        None => {
+            let options = world_options();
+            if options.iter().any(|option| option == "--4d") {
+                reject_4d_files(&options);
+            }
+            let terrain = create_terrain(&options);
+            (None, options, terrain)
+        }
+    };
+
+    // In 4D mode the terrain is the slice of a 4D world cut by the hyperplane,
+    // kept along with the tiles of the slice it was last loaded with.
+    let four_d = options.iter().any(|option| option == "--4d");
+    let mut world4d = (four_d && client.is_none()).then(|| {
+        let world = Terrain4::new(terrain.size());
+        let hyperplane = create_hyperplane(&options, terrain.size());
+        let slice = world.slice(&hyperplane);
+        (world, hyperplane, slice)
+    });
+    if let Some((_, _, slice)) = &world4d {
+        terrain.load(slice.clone());
+        terrain.take_dirty();
+    }
+    let mut level = load_level(&options);
+    if let Some(level) = &level {
+        level.place(&mut terrain);
+    }
+    terrain.rebuild();
+    let mut terrain_view = TerrainView::new(&display, &terrain)?;
 
     let size = terrain.size() as f32;
-    let mut plane = Plane::new(&display, terrain.size());
-    let mut player = Player::new(&display);
+    let (mut views, plane) = Views::new(&view_names(&world_options()), terrain.size());
+    let mut plane_view = PlaneView::new(&display)?;
+    let player = Player::new(&plane);
+    let mut player_view = PlayerView::new(&display)?;
     let mut player_mode = false;
-    let xsection = XSection::new(&display, &terrain);
+    let mut xsection = XSection::new(&display, &terrain)?;
+    let mut contour = Contour::new(&display)?;
+    let mut level_view = LevelView::new(&display)?;
+    let mut entity_view = EntityView::new(&display)?;
+    let mut overlay = Overlay::new(&display)?;
+    let mut editor = Editor::default();
+    let mut history = History::new(history::LIMIT);
+    let default_sun = Sun::default();
+    let sun = Sun::new(
+        arg_vector("--sun-direction").unwrap_or(default_sun.direction),
+        arg_vector("--sun-color").unwrap_or(default_sun.color),
+    );
 
     let perspective = Matrix::new_perspective(
         width / 2.0 / height,
@@ -50,38 +436,58 @@ fn main() {
 
     let identity = Matrix4::identity().into();
 
-    let volume_size = 16;
-    let mut volume_data: Vec<Vec<Vec<(u8, u8, u8)>>> = Vec::with_capacity(volume_size);
-    for x in 0..volume_size {
-        let mut volume_data1 = Vec::with_capacity(volume_size);
-        for y in 0..volume_size * 2 {
-            let mut volume_data2 = Vec::with_capacity(volume_size);
-            for z in 0..volume_size {
-                if y > volume_size * 2 - 3 - (((x * 3 + z) as f32).sin() * 3.0) as usize {
-                    volume_data2.push((42, 110, 40));
-                } else if ((x * 271 + y * 167 + z * 83) as f32).sin() > 0.5 {
-                    volume_data2.push((47, 30, 22));
-                } else {
-                    volume_data2.push((58, 30, 16));
-                }
-            }
-            volume_data1.push(volume_data2);
-        }
-        volume_data.push(volume_data1);
+    // The 2D half of the window is shared by the views, and the depth of each
+    // is kept for drawing its plane in the 3D view.
+    let tiles = views::layout(
+        views.count(),
+        glium::Rect {
+            left: width as u32 / 2,
+            bottom: 0,
+            width: width as u32 / 2,
+            height: height as u32,
+        },
+    );
+    let mut textures = Vec::new();
+    for tile in &tiles {
+        textures.push(glium::texture::DepthTexture2d::empty(&display, tile.width, tile.height)?);
     }
+    let volume = Volume::new(&display, &terrain)?;
+    let mut atlas = Atlas::new(&display)?;
 
-    let texture =
-        glium::texture::DepthTexture2d::empty(&display, width as u32 / 2, height as u32).unwrap();
-    let volume = glium::texture::Texture3d::with_format(
-        &display,
-        volume_data,
-        glium::texture::UncompressedFloatFormat::U8U8U8,
-        glium::texture::MipmapsOption::NoMipmap,
-    )
-    .unwrap();
+    // Shared with the level script, which only runs while these are not borrowed.
+    let terrain_cell = Rc::new(RefCell::new(terrain));
+    let plane_cell = Rc::new(RefCell::new(plane));
+    let player_cell = Rc::new(RefCell::new(player));
+    let entities_cell = Rc::new(RefCell::new(Entities::default()));
+    let mut script = arg_value("--script").map(|path| {
+        Script::load(
+            std::path::Path::new(&path),
+            terrain_cell.clone(),
+            plane_cell.clone(),
+            player_cell.clone(),
+            entities_cell.clone(),
+        )
+        .unwrap_or_else(|error| {
+            eprintln!("Could not load script {}: {}", path, error);
+            std::process::exit(1);
+        })
+    });
 
     let mut keys_held = std::collections::HashSet::new();
+    let mut cursor = (0.0, 0.0);
     let mut last_time = std::time::Instant::now();
+    let mut last_reload = last_time;
+    let mut recorder = arg_value("--record").map(|path| {
+        // What scripts do depends on more than the input, so it cannot be played back.
+        if script.is_some() {
+            eprintln!("Games with a --script cannot be recorded");
+            std::process::exit(1);
+        }
+        Recorder::create(std::path::Path::new(&path), &world_options()).unwrap_or_else(|error| {
+            eprintln!("Could not record to {}: {}", path, error);
+            std::process::exit(1);
+        })
+    });
 
     event_loop.run(move |event, _, control_flow| {
         let delta_time = last_time.elapsed().as_millis() as f32 / 1000.0;
@@ -90,16 +496,150 @@ fn main() {
             last_time + std::time::Duration::from_nanos(16_666_667);
         *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
 
+        let mut tick = Tick::new(delta_time);
+
+        if let Some(script) = &mut script {
+            script.tick(delta_time);
+        }
+        let mut terrain = terrain_cell.borrow_mut();
+        let mut plane = plane_cell.borrow_mut();
+        let mut player = player_cell.borrow_mut();
+        let mut entities = entities_cell.borrow_mut();
+
+        if let glutin::event::Event::WindowEvent { event, .. } = &event {
+            overlay.on_event(event);
+        }
+        // Tile of the world under the cursor in one of the 2D views.
+        let pointed = views::locate(&tiles, cursor, height, terrain.size()).map(|(index, (x, y))| {
+            let point = views.plane(index, &plane).transform() * Vector4::new(x, y, 0.0, 1.0);
+            [point.x, point.y, point.z].map(|c| c.floor() as isize)
+        });
+        if let Some(active) = &mut client {
+            if let Err(error) = active.receive(&mut terrain, &mut plane, &mut player, &mut player_mode) {
+                eprintln!("Lost the connection: {}", error);
+                client = None;
+            }
+        }
+
         match event {
             glutin::event::Event::WindowEvent { event, .. } => match event {
                 glutin::event::WindowEvent::CloseRequested => {
                     *control_flow = glutin::event_loop::ControlFlow::Exit;
                 }
                 glutin::event::WindowEvent::KeyboardInput { input, .. } => {
-                    if keys_held.contains(&VirtualKeyCode::Tab) {
-                        if input.state == glutin::event::ElementState::Released {
-                            player_mode = !player_mode;
-                        } 
+                    if keys_held.contains(&VirtualKeyCode::Tab)
+                        && input.state == glutin::event::ElementState::Released
+                    {
+                        player_mode = !player_mode;
+                        tick.toggle = true;
+                    }
+                    if keys_held.contains(&VirtualKeyCode::V)
+                        && input.state == glutin::event::ElementState::Released
+                        && views.count() > 1
+                    {
+                        // Attach the player to the next view, which is then the plane that moves.
+                        let next = (views.attached() + 1) % views.count();
+                        if client.is_some() {
+                            println!("Views can only be switched in worlds of your own");
+                        } else if views.attach(next, &mut plane, &mut player, &terrain) {
+                            tick.attach = Some(next);
+                            println!("Attached to the {} view", views.name(next));
+                        } else {
+                            println!("There is no room for the player in the {} view", views.name(next));
+                        }
+                    }
+                    if keys_held.contains(&VirtualKeyCode::P)
+                        && input.state == glutin::event::ElementState::Released
+                    {
+                        let path = format!("slice-{}", timestamp());
+                        let hyperplane = world4d.as_ref().map(|(_, hyperplane, _)| hyperplane);
+                        let world = export_options(&options, hyperplane);
+                        match export::export(&terrain, &plane.transform(), &world, std::path::Path::new(&path)) {
+                            Ok(()) => println!("Exported slice to {}.svg and {}.png", path, path),
+                            Err(error) => eprintln!("Could not export slice: {}", error),
+                        }
+                    }
+                    if keys_held.contains(&VirtualKeyCode::G)
+                        && input.state == glutin::event::ElementState::Released
+                        && player_mode
+                    {
+                        // Throw a ball along the plane in the direction the player faces.
+                        let forward = plane.transform().column(0).xyz() * player.facing();
+                        entities.spawn(Entity {
+                            position: player.world_position() + Vector3::new(0.0, 0.3, 0.0),
+                            extent: 0.15,
+                            appearance: Appearance::Sprite([0.9, 0.9, 0.9]),
+                            body: Some(Body {
+                                velocity: forward * 8.0 + Vector3::new(0.0, 5.0, 0.0),
+                                gravity: true,
+                                bounce: 0.5,
+                            }),
+                            lifetime: Some(entity::BALL_LIFETIME),
+                        });
+                    }
+                    if keys_held.contains(&VirtualKeyCode::F3)
+                        && input.state == glutin::event::ElementState::Released
+                    {
+                        overlay.visible = !overlay.visible;
+                    }
+                    if keys_held.contains(&VirtualKeyCode::F2)
+                        && input.state == glutin::event::ElementState::Released
+                    {
+                        if client.is_some() {
+                            println!("The editor only works on worlds of your own");
+                        } else {
+                            editor.active = !editor.active;
+                        }
+                        let title = if editor.active {
+                            format!("XSection - Editor: {}", editor.describe())
+                        } else {
+                            "XSection".to_string()
+                        };
+                        display.gl_window().window().set_title(&title);
+                    }
+                    let control = keys_held.contains(&VirtualKeyCode::LControl)
+                        || keys_held.contains(&VirtualKeyCode::RControl);
+                    if control
+                        && input.state == glutin::event::ElementState::Released
+                        && client.is_none()
+                    {
+                        // Undo and redo block edits, whether made in play or in the editor.
+                        match input.virtual_keycode {
+                            Some(VirtualKeyCode::Z) => {
+                                history.undo(&mut terrain);
+                                tick.undo = true;
+                            }
+                            Some(VirtualKeyCode::Y) => {
+                                history.redo(&mut terrain);
+                                tick.redo = true;
+                            }
+                            _ => (),
+                        }
+                    }
+                    if editor.active && input.state == glutin::event::ElementState::Released {
+                        if let Some(key) = input.virtual_keycode {
+                            if editor.key(key) {
+                                let title = format!("XSection - Editor: {}", editor.describe());
+                                display.gl_window().window().set_title(&title);
+                            }
+                            if control && key == VirtualKeyCode::S {
+                                let path = arg_value("--world")
+                                    .unwrap_or_else(|| format!("world-{}.world", timestamp()));
+                                match world::save(&terrain, std::path::Path::new(&path)) {
+                                    Ok(()) => println!("Saved the world to {}", path),
+                                    Err(error) => eprintln!("Could not save the world: {}", error),
+                                }
+                            }
+                        }
+                    }
+                    if keys_held.contains(&VirtualKeyCode::M)
+                        && input.state == glutin::event::ElementState::Released
+                    {
+                        let path = format!("terrain-{}", timestamp());
+                        match mesh::export(&terrain, std::path::Path::new(&path)) {
+                            Ok(()) => println!("Exported mesh to {}.obj and {}.glb", path, path),
+                            Err(error) => eprintln!("Could not export mesh: {}", error),
+                        }
                     }
 
                     match input.state {
@@ -108,6 +648,51 @@ fn main() {
                     };
 
                 }
+                glutin::event::WindowEvent::CursorMoved { position, .. } => {
+                    let scale_factor = display.gl_window().window().scale_factor();
+                    let position = position.to_logical::<f32>(scale_factor);
+                    cursor = (position.x, position.y);
+                }
+                glutin::event::WindowEvent::MouseInput {
+                    state: glutin::event::ElementState::Pressed,
+                    button,
+                    ..
+                } if editor.active && !overlay.wants_pointer() => {
+                    let erase = button == glutin::event::MouseButton::Right;
+                    if let Some(tile) = pointed {
+                        // Paint around the tile under the cursor in a 2D view.
+                        let value = if erase { block::AIR } else { editor.block };
+                        let tiles = editor.paint(&mut terrain, &mut history, tile, value);
+                        tick.paint = Some((value, tiles));
+                    } else if cursor.0 < width / 2.0 {
+                        tick.paint = editor.click(&mut terrain, &mut history, erase);
+                    }
+                }
+                glutin::event::WindowEvent::MouseInput {
+                    state: glutin::event::ElementState::Pressed,
+                    button: glutin::event::MouseButton::Left,
+                    ..
+                } if pointed.is_some() && !overlay.wants_pointer() => {
+                    // Break the tile under the cursor in a 2D view.
+                    let tile = pointed.unwrap();
+                    // A shared world cannot be undone, as the server breaks it too, and
+                    // only tiles on the plane within reach of the player break there.
+                    let old = match client {
+                        Some(_) if !network::can_break(&plane, &player, tile) => None,
+                        Some(_) => terrain.set(tile[0], tile[1], tile[2], block::AIR),
+                        None => history.set(&mut terrain, tile, block::AIR),
+                    };
+                    if old.is_some() {
+                        tick.breaks.push(tile);
+                    }
+                    if let Some(old) = old {
+                        if old != block::AIR {
+                            if let Some(script) = &mut script {
+                                script.push_event(Event::BlockBroken(tile, old));
+                            }
+                        }
+                    }
+                }
                 _ => (),
             },
             glutin::event::Event::NewEvents(cause) => match cause {
@@ -121,42 +706,71 @@ fn main() {
         if keys_held.contains(&VirtualKeyCode::Escape) {
             *control_flow = glutin::event_loop::ControlFlow::Exit;
         }
-        if keys_held.contains(&VirtualKeyCode::W) {
-            if player_mode {
-            } else {
-                plane.strafe_x(delta_time, true);
-            }
+        // Keys pressed along with Ctrl, such as the S of Ctrl+S or the Z of
+        // undo, move nothing.
+        let control = keys_held.contains(&VirtualKeyCode::LControl)
+            || keys_held.contains(&VirtualKeyCode::RControl);
+        if !control {
+            tick.hold(&keys_held);
         }
-        if keys_held.contains(&VirtualKeyCode::S) {
-            if player_mode {
-            } else {
-                plane.strafe_x(delta_time, false);
+        if let Some((world, hyperplane, slice)) = &mut world4d {
+            if replay::step_hyperplane(&tick, world, hyperplane, slice, &mut terrain) {
+                // The doors still closed stand in every slice.
+                if let Some(level) = &level {
+                    level.place(&mut terrain);
+                }
+                // Edits were made to the previous slice and no longer apply.
+                history.clear();
             }
         }
-        if keys_held.contains(&VirtualKeyCode::A) {
-            if player_mode {
-                player.walk(&plane, &terrain, delta_time, true);
-            } else {
-                plane.strafe_z(delta_time, true);
-            }
+        if client.is_some() {
+            tick.delta_time = tick.delta_time.min(network::MAX_DELTA);
         }
-        if keys_held.contains(&VirtualKeyCode::D) {
-            if player_mode {
-                player.walk(&plane, &terrain, delta_time, false);
-            } else {
-                plane.strafe_z(delta_time, false);
+        replay::step(&tick, player_mode, &mut plane, &mut player, &terrain);
+        if let Some(active) = &mut client {
+            if let Err(error) = active.send(&tick) {
+                eprintln!("Lost the connection: {}", error);
+                client = None;
             }
         }
-        if keys_held.contains(&VirtualKeyCode::Space) {
-            if player_mode {
-                player.jump();
+        if let Some(active) = &mut recorder {
+            if let Err(error) = active.record(&tick) {
+                eprintln!("Stopped recording: {}", error);
+                recorder = None;
             }
         }
-        if keys_held.contains(&VirtualKeyCode::Q) {
-            plane.rotate(delta_time, true);
+        let offsets = terrain.periodic_offsets(&plane.corners());
+
+        if editor.active {
+            // Follow the mouse through the 3D view in the left half of the window.
+            let ray = (cursor.0 < width / 2.0).then(|| {
+                let x = cursor.0 / (width / 2.0) * 2.0 - 1.0;
+                let y = 1.0 - cursor.1 / height * 2.0;
+                editor::pick_ray(&view, &perspective, (x, y))
+            });
+            editor.hover(&terrain, ray);
+        }
+
+        // Edited shaders and textures are picked up while the game runs.
+        if last_reload.elapsed() >= std::time::Duration::from_millis(500) {
+            last_reload = std::time::Instant::now();
+            terrain_view.reload(&display);
+            plane_view.reload(&display);
+            player_view.reload(&display);
+            xsection.reload(&display);
+            contour.reload(&display);
+            level_view.reload(&display);
+            entity_view.reload(&display);
+            overlay.reload(&display);
+            atlas.reload(&display);
         }
-        if keys_held.contains(&VirtualKeyCode::E) {
-            plane.rotate(delta_time, false);
+
+        if let Some((min, max)) = terrain.take_dirty() {
+            volume.update(&display, &terrain, min, max);
+            terrain.rebuild();
+            terrain_view.update(&display, &terrain);
+            xsection.update(&display, &terrain);
+            contour.invalidate();
         }
 
         let mut target = display.draw();
@@ -180,43 +794,148 @@ fn main() {
             ..Default::default()
         };
 
-        let framebuffer = glium::framebuffer::SimpleFrameBuffer::depth_only(&display, &texture).unwrap();
-
-        let volume_sampler = glium::uniforms::Sampler::new(&volume)
-            .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
-            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest);
+        let volume_sampler = volume.sampler();
 
         let orthographic = orthographic.into();
 
-        xsection.draw(
-            &mut target,
-            &params,
-            orthographic,
-            &plane,
-            volume_sampler,
-        );
-        framebuffer.blit_buffers_from_frame(
-            &params.viewport.unwrap(),
-            &glium::BlitTarget {
-                left: 0,
-                bottom: 0,
-                width: width as i32 / 2,
-                height: height as i32,
-            },
-            glium::uniforms::MagnifySamplerFilter::Nearest,
-            glium::BlitMask {
-                color: false,
-                depth: true,
-                stencil: false,
-            },
-        );
-        player.draw(
-            &mut target,
-            &params,
-            identity,
-            orthographic,
-            identity,
-        );
+        // Every view in its own tile of the right half of the window.
+        for (index, tile) in tiles.iter().enumerate() {
+            let view_plane = views.plane(index, &plane);
+            let transform = view_plane.transform();
+            params.viewport = Some(*tile);
+            let line_params = glium::DrawParameters {
+                depth: Default::default(),
+                ..params.clone()
+            };
+
+            xsection.draw(
+                &mut target,
+                &params,
+                orthographic,
+                view_plane,
+                volume_sampler,
+                &atlas,
+                &terrain.periodic_offsets(&view_plane.corners()),
+                &sun,
+            );
+            if terrain.style() == Style::Smooth {
+                contour.update(&display, &terrain, index, view_plane);
+                contour.draw(
+                    index,
+                    &mut target,
+                    &params,
+                    orthographic,
+                    volume_sampler,
+                    &atlas,
+                    &sun,
+                );
+            }
+            let framebuffer =
+                glium::framebuffer::SimpleFrameBuffer::depth_only(&display, &textures[index]).unwrap();
+            framebuffer.blit_buffers_from_frame(
+                tile,
+                &glium::BlitTarget {
+                    left: 0,
+                    bottom: 0,
+                    width: tile.width as i32,
+                    height: tile.height as i32,
+                },
+                glium::uniforms::MagnifySamplerFilter::Nearest,
+                glium::BlitMask {
+                    color: false,
+                    depth: true,
+                    stencil: false,
+                },
+            );
+            if overlay.grid {
+                overlay.draw_grid(
+                    &display,
+                    &mut target,
+                    &line_params,
+                    orthographic,
+                    &transform,
+                    terrain.size(),
+                );
+            }
+            if let Some(level) = &level {
+                level_view.draw(
+                    &mut target,
+                    &params,
+                    orthographic,
+                    level,
+                    &transform,
+                    terrain.size(),
+                );
+            }
+            entity_view.draw_section(
+                &display,
+                &mut target,
+                &params,
+                orthographic,
+                &entities,
+                &transform,
+            );
+            if index == views.attached()
+                || plane::on_plane(&transform, player.world_position(), terrain.size()).is_some()
+            {
+                player_view.draw(
+                    &display,
+                    &mut target,
+                    &params,
+                    &player,
+                    view_plane,
+                    identity,
+                    orthographic,
+                    &Matrix4::identity(),
+                );
+            }
+            if views.count() > 1 {
+                // A border in the colour of the view, and lines where the others cross it.
+                let [r, g, b] = views.color(index);
+                let half = size / 2.0 * 0.995;
+                let border = [[-half, -half], [half, -half], [half, half], [-half, half]];
+                let border: Vec<[[f32; 2]; 2]> = (0..4).map(|i| [border[i], border[(i + 1) % 4]]).collect();
+                overlay.draw_segments(&display, &mut target, &line_params, orthographic, &border, [r, g, b, 1.0]);
+                for other in (0..views.count()).filter(|&other| other != index) {
+                    let other_transform = views.plane(other, &plane).transform();
+                    if let Some(segment) = plane::intersection(&transform, &other_transform, terrain.size()) {
+                        let [r, g, b] = views.color(other);
+                        overlay.draw_segments(
+                            &display,
+                            &mut target,
+                            &line_params,
+                            orthographic,
+                            &[segment],
+                            [r, g, b, 0.8],
+                        );
+                    }
+                }
+            }
+            if let Some(client) = &client {
+                // Where the planes of others cross this one, and others standing in it.
+                let mut avatars = Vec::new();
+                for other in &client.others {
+                    let [r, g, b] = other.color();
+                    if let Some(segment) = plane::intersection(&transform, &other.transform, terrain.size()) {
+                        overlay.draw_segments(
+                            &display,
+                            &mut target,
+                            &line_params,
+                            orthographic,
+                            &[segment],
+                            [r, g, b, 1.0],
+                        );
+                    }
+                    if let Some(position) = plane::on_plane(&transform, other.motion.position, terrain.size()) {
+                        avatars.push(Instance {
+                            model: player_view.model(&Matrix4::identity(), position),
+                            color: [r, g, b, 1.0],
+                        });
+                    }
+                }
+                player_view.draw_instances(&display, &mut target, &params, identity, orthographic, &avatars);
+            }
+        }
 
         params.viewport = Some(glium::Rect {
             left: 0,
@@ -224,27 +943,153 @@ fn main() {
             width: width as u32 / 2,
             height: height as u32,
         });
-        terrain.draw(&mut target, &params, view, perspective, volume_sampler);
+        let terrain_params = if overlay.wireframe {
+            glium::DrawParameters {
+                polygon_mode: glium::PolygonMode::Line,
+                ..params.clone()
+            }
+        } else {
+            params.clone()
+        };
+        terrain_view.draw(
+            &mut target,
+            &terrain_params,
+            view,
+            perspective,
+            volume_sampler,
+            &atlas,
+            &offsets,
+            &sun,
+        );
 
-        params.depth = Default::default();
-        plane.draw(
+        entity_view.draw(
             &mut target,
             &params,
             view,
             perspective,
-            &texture,
-            width,
-            height,
+            &entities,
+            &sun,
         );
-        player.draw(
+
+        params.depth = Default::default();
+        for (index, tile) in tiles.iter().enumerate() {
+            plane_view.draw(
+                &display,
+                &mut target,
+                &params,
+                views.plane(index, &plane),
+                view,
+                perspective,
+                &textures[index],
+                tile.width as f32,
+                tile.height as f32,
+            );
+        }
+        player_view.draw(
+            &display,
             &mut target,
             &params,
+            &player,
+            &plane,
             view,
             perspective,
-            plane.transform().into(),
+            &plane.transform(),
+        );
+        if let Some(client) = &client {
+            // Everyone else's plane, and their player on it.
+            let mut planes = Vec::new();
+            let mut avatars = Vec::new();
+            for other in &client.others {
+                let [r, g, b] = other.color();
+                planes.push(Instance {
+                    model: PlaneView::model(&other.transform, terrain.size()),
+                    color: [r, g, b, 0.2],
+                });
+                if let Some(position) = plane::on_plane(&other.transform, other.motion.position, terrain.size()) {
+                    avatars.push(Instance {
+                        model: player_view.model(&other.transform, position),
+                        color: [r, g, b, 1.0],
+                    });
+                }
+            }
+            plane_view.draw_instances(&display, &mut target, &params, view, perspective, &planes);
+            player_view.draw_instances(&display, &mut target, &params, view, perspective, &avatars);
+        }
+        if editor.active {
+            if let Some(cursor) = editor.cursor(true) {
+                overlay.draw_box(
+                    &display,
+                    &mut target,
+                    &params,
+                    view,
+                    perspective,
+                    cursor,
+                    [0.9, 0.2, 0.1, 1.0],
+                );
+            }
+            if let Some(cursor) = editor.cursor(false) {
+                overlay.draw_box(
+                    &display,
+                    &mut target,
+                    &params,
+                    view,
+                    perspective,
+                    cursor,
+                    [1.0, 1.0, 1.0, 1.0],
+                );
+            }
+        }
+        // With several views their outlines tell them apart, so they are always drawn.
+        if overlay.outline || views.count() > 1 {
+            for index in 0..views.count() {
+                let [r, g, b] = views.color(index);
+                overlay.draw_outline(
+                    &display,
+                    &mut target,
+                    &params,
+                    view,
+                    perspective,
+                    &views.plane(index, &plane).corners(),
+                    [r, g, b, 1.0],
+                );
+            }
+        }
+
+        overlay.draw(
+            &display,
+            &mut target,
+            &Stats {
+                vertices: terrain.vertices().len(),
+                lines: terrain.lines().len(),
+                transform: plane.transform(),
+                player: player.position(&plane),
+                player_world: player.world_position(),
+                on_floor: player.on_floor(),
+            },
         );
         target.finish().unwrap();
 
-        player.update(delta_time, &plane, &terrain);
+        entities.update(delta_time, &terrain);
+
+        if let Some(level) = &mut level {
+            let was_won = level.won();
+            let position = player.world_position();
+            for message in level.update(&mut terrain, &plane.transform(), position) {
+                println!("{}", message);
+            }
+            if level.won() && !was_won {
+                display.gl_window().window().set_title("XSection - Level complete");
+            }
+        }
+
+        if *control_flow == glutin::event_loop::ControlFlow::Exit {
+            // Dropping the client tells the server we left.
+            client = None;
+            if let Some(recorder) = recorder.take() {
+                if let Err(error) = recorder.finish(&State::of(&plane, &player)) {
+                    eprintln!("Could not finish the recording: {}", error);
+                }
+            }
+        }
     });
 }
@@ -0,0 +1,234 @@
+//! Writes the 3D terrain mesh to Wavefront OBJ and binary glTF, with normals
+//! and a colour per triangle taken from the block it belongs to.
+
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+
+use super::block;
+use super::terrain::{Terrain, Vertex};
+
+/// One corner of an exported triangle.
+struct Corner {
+    position: [f32; 3],
+    normal: [f32; 3],
+    color: [f32; 3],
+}
+
+/// Colour of a triangle: the colour of the tile just behind its centre when
+/// that tile has one of its own, the palette colour of its block otherwise.
+fn triangle_color(terrain: &Terrain, triangle: &[Vertex]) -> [f32; 3] {
+    let mut inside = [0.0; 3];
+    for vertex in triangle {
+        for (c, (p, n)) in inside
+            .iter_mut()
+            .zip(vertex.position.iter().zip(vertex.normal.iter()))
+        {
+            *c += p / 3.0 - n / 6.0;
+        }
+    }
+
+    let size = terrain.size() as f32;
+    let (r, g, b) = if inside.iter().all(|&c| c >= 0.0 && c < size) {
+        terrain.color(inside[0] as usize, inside[1] as usize, inside[2] as usize)
+    } else {
+        None
+    }
+    .unwrap_or_else(|| block::color(triangle[0].tile as u8));
+    [r, g, b].map(|c| c as f32 / 255.0)
+}
+
+fn corners(terrain: &Terrain) -> Vec<Corner> {
+    terrain
+        .vertices()
+        .chunks_exact(3)
+        .flat_map(|triangle| {
+            let color = triangle_color(terrain, triangle);
+            triangle.iter().map(move |vertex| Corner {
+                position: vertex.position,
+                normal: vertex.normal,
+                color,
+            })
+        })
+        .collect()
+}
+
+/// Writes an OBJ with the colour appended to every vertex position, which
+/// Blender and most other tools read as vertex colours.
+pub fn write_obj(terrain: &Terrain, path: &Path) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(
+        file,
+        "# XSection terrain, {0}x{0}x{0} tiles",
+        terrain.size()
+    )?;
+    writeln!(file, "o terrain")?;
+
+    let corners = corners(terrain);
+    for corner in corners.iter() {
+        let [x, y, z] = corner.position;
+        let [r, g, b] = corner.color;
+        writeln!(file, "v {} {} {} {} {} {}", x, y, z, r, g, b)?;
+    }
+    for corner in corners.iter() {
+        let [x, y, z] = corner.normal;
+        writeln!(file, "vn {} {} {}", x, y, z)?;
+    }
+    for i in (1..=corners.len()).step_by(3) {
+        writeln!(file, "f {0}//{0} {1}//{1} {2}//{2}", i, i + 1, i + 2)?;
+    }
+
+    file.flush()
+}
+
+/// glTF wants linear colours, the palette is in sRGB.
+fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Writes a binary glTF holding a single mesh with positions, normals and
+/// colours stored one after another in its buffer.
+pub fn write_glb(terrain: &Terrain, path: &Path) -> std::io::Result<()> {
+    let corners = corners(terrain);
+    if corners.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the terrain has no geometry to export",
+        ));
+    }
+
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for corner in corners.iter() {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(corner.position[axis]);
+            max[axis] = max[axis].max(corner.position[axis]);
+        }
+    }
+
+    let mut binary = Vec::new();
+    let positions = corners.iter().map(|c| c.position);
+    let normals = corners.iter().map(|c| c.normal);
+    let colors = corners.iter().map(|c| c.color.map(to_linear));
+    for value in positions.chain(normals).chain(colors).flatten() {
+        binary.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let count = corners.len();
+    let view = count * 12;
+    let json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"xsection"}},"#,
+            r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0,"name":"terrain"}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"mode":4}}]}}],"#,
+            r#""buffers":[{{"byteLength":{length}}}],"#,
+            r#""bufferViews":["#,
+            r#"{{"buffer":0,"byteOffset":0,"byteLength":{view},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{view},"byteLength":{view},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{view},"target":34962}}],"#,
+            r#""accessors":["#,
+            r#"{{"bufferView":0,"componentType":5126,"count":{count},"type":"VEC3","min":{min:?},"max":{max:?}}},"#,
+            r#"{{"bufferView":1,"componentType":5126,"count":{count},"type":"VEC3"}},"#,
+            r#"{{"bufferView":2,"componentType":5126,"count":{count},"type":"VEC3"}}]}}"#
+        ),
+        length = binary.len(),
+        view = view,
+        offset = view * 2,
+        count = count,
+        min = min,
+        max = max,
+    );
+
+    // Both chunks are padded to four bytes, JSON with spaces and binary with zeros.
+    let mut json = json.into_bytes();
+    json.resize((json.len() + 3) & !3, b' ');
+    binary.resize((binary.len() + 3) & !3, 0);
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let total = 12 + 8 + json.len() + 8 + binary.len();
+    file.write_all(b"glTF")?;
+    file.write_all(&2u32.to_le_bytes())?;
+    file.write_all(&(total as u32).to_le_bytes())?;
+    file.write_all(&(json.len() as u32).to_le_bytes())?;
+    file.write_all(b"JSON")?;
+    file.write_all(&json)?;
+    file.write_all(&(binary.len() as u32).to_le_bytes())?;
+    file.write_all(b"BIN\0")?;
+    file.write_all(&binary)?;
+    file.flush()
+}
+
+/// Exports the mesh to `path`, picking the format from its extension. Without
+/// a known extension both an OBJ and a GLB are written next to each other.
+pub fn export(terrain: &Terrain, path: &Path) -> std::io::Result<()> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("obj") => write_obj(terrain, path),
+        Some("glb") => write_glb(terrain, path),
+        _ => {
+            write_obj(terrain, &path.with_extension("obj"))?;
+            write_glb(terrain, &path.with_extension("glb"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smooth::Style;
+
+    #[test]
+    fn single_voxel_exports_outward_facing_triangles() {
+        let mut terrain =
+            Terrain::from_tiles(1, vec![block::STONE], vec![None], false, Style::Blocky);
+        terrain.rebuild();
+        let path = std::env::temp_dir().join(format!("xsection-{}.obj", std::process::id()));
+        write_obj(&terrain, &path).unwrap();
+        let source = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let numbers = |line: &str| -> Vec<f32> {
+            line.split_whitespace()
+                .skip(1)
+                .map(|word| word.parse().unwrap())
+                .collect()
+        };
+        let positions: Vec<Vec<f32>> = source
+            .lines()
+            .filter(|line| line.starts_with("v "))
+            .map(numbers)
+            .collect();
+        let normals: Vec<Vec<f32>> = source
+            .lines()
+            .filter(|line| line.starts_with("vn "))
+            .map(numbers)
+            .collect();
+        let faces: Vec<Vec<usize>> = source
+            .lines()
+            .filter(|line| line.starts_with("f "))
+            .map(|line| {
+                line.split_whitespace()
+                    .skip(1)
+                    .map(|corner| corner.split('/').next().unwrap().parse().unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(faces.len(), 12);
+
+        for face in faces {
+            let [a, b, c] = [0, 1, 2].map(|i| &positions[face[i] - 1]);
+            let normal = &normals[face[0] - 1];
+            let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let cross = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            let facing = cross[0] * normal[0] + cross[1] * normal[1] + cross[2] * normal[2];
+            assert!(facing > 0.0, "{:?} winds against {:?}", face, normal);
+        }
+    }
+}
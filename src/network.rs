@@ -0,0 +1,775 @@
+//! Shared worlds over UDP. `xsection server` owns the terrain and moves every
+//! client's plane and player by the ticks of input they send, the same way
+//! `replay::step` moves them in a single game. Clients move their own plane
+//! and player straight away and correct them when a snapshot arrives, by
+//! stepping again through the ticks the server has not seen yet.
+//!
+//! Every datagram is a few lines of text:
+//!
+//! ```text
+//! hello                             client joins
+//! welcome 3                         server answers with the client's id and,
+//! --wrap                            one per line, the world options
+//! world 8192                        client asks for the world from byte 8192
+//! world 8192 20000                  server sends the next part of the 20000
+//! 0000000000000000                  bytes of its world file
+//! input 57 12 tick 0.016 WA         client's tick 57, knowing 12 block changes
+//! snapshot 14 57                    14 block changes so far, tick 57 applied
+//! client 3 player <16 numbers> x y z vel_y floor facing
+//! blocks 12                         block changes from number 12 on
+//! 4 5 6 0
+//! bye                               client leaves
+//! ```
+//!
+//! The world is sent as it was when the server started, so clients load the
+//! same tiles whatever files they have, and then catch up on the block
+//! changes. These are numbered in the order the server made them. Clients
+//! report how many they have with every input, and the server sends the rest
+//! again with every snapshot until they do, so none are lost. Clients in turn
+//! send the tiles they broke with every input until the server has stepped
+//! one of them.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use nalgebra::base::{Matrix4, Vector3};
+
+use super::block;
+use super::plane::Plane;
+use super::player::{Motion, Player};
+use super::replay::{self, Tick};
+use super::smooth::Style;
+use super::terrain::Terrain;
+use super::world;
+
+/// Port the server listens on without `--port`.
+pub const PORT: u16 = 7878;
+
+/// Time between two snapshots sent to each client.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Clients the server has not heard from for this long have left.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest tick the server steps, so a slow client cannot move in big jumps.
+pub const MAX_DELTA: f32 = 0.25;
+
+/// Farthest from its player, in tiles, a client breaks blocks.
+pub const REACH: f32 = 5.0;
+
+/// Most block changes sent in one datagram.
+const BLOCKS_PER_DATAGRAM: usize = 256;
+
+/// Most bytes of the world file sent in one datagram.
+const WORLD_PER_DATAGRAM: usize = 8192;
+
+/// Times a client asks the server before giving up, and how long it waits
+/// for each answer.
+const ATTEMPTS: usize = 12;
+const ATTEMPT_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Colours told apart by client id.
+const COLORS: [[f32; 3]; 6] = [
+    [0.2, 0.8, 1.0],
+    [1.0, 0.4, 0.3],
+    [0.5, 1.0, 0.3],
+    [1.0, 0.8, 0.2],
+    [0.8, 0.4, 1.0],
+    [1.0, 0.5, 0.8],
+];
+
+/// A client's plane and player as last seen in a snapshot.
+#[derive(Clone, Debug)]
+pub struct Remote {
+    pub id: u32,
+    pub player_mode: bool,
+    pub transform: Matrix4<f32>,
+    pub motion: Motion,
+}
+
+impl Remote {
+    /// Colour the client's plane and player are drawn in.
+    pub fn color(&self) -> [f32; 3] {
+        COLORS[self.id as usize % COLORS.len()]
+    }
+
+    fn write(&self) -> String {
+        let transform: Vec<String> = self.transform.iter().map(|v| v.to_string()).collect();
+        let motion = &self.motion;
+        format!(
+            "client {} {} {} {} {} {} {} {} {}",
+            self.id,
+            if self.player_mode { "player" } else { "plane" },
+            transform.join(" "),
+            motion.position.x,
+            motion.position.y,
+            motion.position.z,
+            motion.vel_y,
+            if motion.on_floor { "floor" } else { "air" },
+            motion.facing
+        )
+    }
+
+    fn parse(words: &[&str]) -> Option<Remote> {
+        let (id, mode, values) = match words {
+            [id, mode, values @ ..] if values.len() == 22 => (id, mode, values),
+            _ => return None,
+        };
+        let numbers = |words: &[&str]| {
+            words
+                .iter()
+                .map(|word| word.parse::<f32>().ok())
+                .collect::<Option<Vec<f32>>>()
+        };
+        let transform = Matrix4::from_column_slice(&numbers(&values[..16])?);
+        let position = numbers(&values[16..20])?;
+        // Planes are drawn and tested against through the inverse transform.
+        if !transform.iter().chain(&position).all(|v| v.is_finite())
+            || transform.try_inverse().is_none()
+        {
+            return None;
+        }
+        Some(Remote {
+            id: id.parse().ok()?,
+            player_mode: *mode == "player",
+            transform,
+            motion: Motion {
+                position: Vector3::new(position[0], position[1], position[2]),
+                vel_y: position[3],
+                on_floor: values[20] == "floor",
+                facing: values[21].parse().ok()?,
+            },
+        })
+    }
+}
+
+/// Whether a client may break `tile`: the tile has to be cut by its plane and
+/// within reach of its player. The server drops every other break, so clients
+/// check before breaking anything themselves.
+pub fn can_break(plane: &Plane, player: &Player, tile: [isize; 3]) -> bool {
+    let centre = Vector3::new(tile[0] as f32, tile[1] as f32, tile[2] as f32).add_scalar(0.5);
+    let Some(inverse) = plane.transform().try_inverse() else {
+        return false;
+    };
+    // A plane through a tile passes at most half its diagonal from the centre.
+    let local = inverse * centre.push(1.0);
+    local.z.abs() <= 3.0f32.sqrt() / 2.0 && (centre - player.world_position()).norm() <= REACH
+}
+
+/// A client as the server keeps it.
+struct Peer {
+    id: u32,
+    plane: Plane,
+    player: Player,
+    player_mode: bool,
+    /// Number of the last tick of input stepped.
+    sequence: u64,
+    /// Block changes the client has.
+    known: usize,
+    last_heard: Instant,
+}
+
+pub struct Server {
+    socket: UdpSocket,
+    terrain: Terrain,
+    options: Vec<String>,
+    /// The terrain as it was when the server started, as a world file.
+    world: Vec<u8>,
+    peers: HashMap<SocketAddr, Peer>,
+    next_id: u32,
+    /// Every block change so far, in order.
+    changes: Vec<([isize; 3], u8)>,
+    last_snapshot: Instant,
+}
+
+impl Server {
+    /// Listens on `address` for clients of a world created with `options`.
+    pub fn bind<A: ToSocketAddrs>(
+        address: A,
+        terrain: Terrain,
+        options: Vec<String>,
+    ) -> Result<Server> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(SNAPSHOT_INTERVAL / 4))?;
+        let mut world = Vec::new();
+        world::write(&terrain, &mut world)?;
+        Ok(Server {
+            socket,
+            terrain,
+            options,
+            world,
+            peers: HashMap::new(),
+            next_id: 1,
+            changes: Vec::new(),
+            last_snapshot: Instant::now(),
+        })
+    }
+
+    /// Address the server listens on, with the port picked when bound to 0.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Serves clients until the socket can no longer receive.
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            self.poll()?;
+        }
+    }
+
+    /// Answers at most one datagram, waiting a little for it, and sends the
+    /// snapshots when they are due.
+    pub fn poll(&mut self) -> Result<()> {
+        let mut buffer = [0; 65536];
+        match self.socket.recv_from(&mut buffer) {
+            Ok((length, from)) => {
+                if let Ok(message) = std::str::from_utf8(&buffer[..length]) {
+                    if let Err(error) = self.receive(from, message) {
+                        eprintln!("Could not answer {}: {}", from, error);
+                    }
+                }
+            }
+            // Windows reports datagrams that bounced off a closed client.
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionReset
+                ) => {}
+            Err(error) => return Err(error),
+        }
+
+        if self.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+            self.last_snapshot = Instant::now();
+            self.peers.retain(|_, peer| {
+                let alive = peer.last_heard.elapsed() < TIMEOUT;
+                if !alive {
+                    println!("Client {} timed out", peer.id);
+                }
+                alive
+            });
+            if let Err(error) = self.broadcast() {
+                eprintln!("Could not send snapshots: {}", error);
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, from: SocketAddr, message: &str) -> Result<()> {
+        let words: Vec<&str> = message.split_whitespace().collect();
+        match words.as_slice() {
+            ["hello"] => {
+                let id = match self.peers.get(&from) {
+                    // The welcome got lost and the client asks again.
+                    Some(peer) => peer.id,
+                    None => {
+                        let id = self.next_id;
+                        self.next_id += 1;
+                        let plane = Plane::new(self.terrain.size());
+                        let player = Player::new(&plane);
+                        self.peers.insert(
+                            from,
+                            Peer {
+                                id,
+                                plane,
+                                player,
+                                player_mode: false,
+                                sequence: 0,
+                                known: 0,
+                                last_heard: Instant::now(),
+                            },
+                        );
+                        println!("Client {} joined from {}", id, from);
+                        id
+                    }
+                };
+                let mut welcome = format!("welcome {}", id);
+                for option in &self.options {
+                    welcome += &format!("\n{}", option);
+                }
+                self.socket.send_to(welcome.as_bytes(), from)?;
+            }
+            ["world", offset] => {
+                let (Some(peer), Ok(offset)) = (self.peers.get_mut(&from), offset.parse::<usize>())
+                else {
+                    return Ok(());
+                };
+                peer.last_heard = Instant::now();
+                let offset = offset.min(self.world.len());
+                let end = (offset + WORLD_PER_DATAGRAM).min(self.world.len());
+                let mut part = format!("world {} {}\n", offset, self.world.len()).into_bytes();
+                part.extend_from_slice(&self.world[offset..end]);
+                self.socket.send_to(&part, from)?;
+            }
+            ["input", sequence, known, "tick", tick @ ..] => {
+                let (Some(peer), Ok(sequence), Ok(known), Ok(tick)) = (
+                    self.peers.get_mut(&from),
+                    sequence.parse::<u64>(),
+                    known.parse::<usize>(),
+                    Tick::parse(tick, 0),
+                ) else {
+                    return Ok(());
+                };
+                peer.last_heard = Instant::now();
+                peer.known = known.min(self.changes.len());
+                // Ticks that arrive late were already given up on by the client.
+                if sequence <= peer.sequence {
+                    return Ok(());
+                }
+                peer.sequence = sequence;
+
+                if tick.toggle {
+                    peer.player_mode = !peer.player_mode;
+                }
+                for &tile in &tick.breaks {
+                    if !can_break(&peer.plane, &peer.player, tile) {
+                        continue;
+                    }
+                    if let Some(old) = self.terrain.set(tile[0], tile[1], tile[2], block::AIR) {
+                        if old != block::AIR {
+                            self.changes.push((tile, block::AIR));
+                        }
+                    }
+                }
+                let tick = Tick {
+                    delta_time: tick.delta_time.clamp(0.0, MAX_DELTA),
+                    ..tick
+                };
+                replay::step(
+                    &tick,
+                    peer.player_mode,
+                    &mut peer.plane,
+                    &mut peer.player,
+                    &self.terrain,
+                );
+            }
+            ["bye"] => {
+                if let Some(peer) = self.peers.remove(&from) {
+                    println!("Client {} left", peer.id);
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Sends every client where everyone is and the block changes it lacks.
+    fn broadcast(&self) -> Result<()> {
+        let clients: Vec<String> = self
+            .peers
+            .values()
+            .map(|peer| {
+                Remote {
+                    id: peer.id,
+                    player_mode: peer.player_mode,
+                    transform: peer.plane.transform(),
+                    motion: peer.player.motion(),
+                }
+                .write()
+            })
+            .collect();
+
+        for (address, peer) in &self.peers {
+            let snapshot = format!(
+                "snapshot {} {}\n{}",
+                self.changes.len(),
+                peer.sequence,
+                clients.join("\n")
+            );
+            self.socket.send_to(snapshot.as_bytes(), address)?;
+
+            if peer.known < self.changes.len() {
+                let mut blocks = format!("blocks {}", peer.known);
+                for ([x, y, z], value) in
+                    self.changes[peer.known..].iter().take(BLOCKS_PER_DATAGRAM)
+                {
+                    blocks += &format!("\n{} {} {} {}", x, y, z, value);
+                }
+                self.socket.send_to(blocks.as_bytes(), address)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Client {
+    socket: UdpSocket,
+    pub id: u32,
+    /// Options the server's world was created with. The world itself comes
+    /// from the server, so files they name are not read.
+    pub options: Vec<String>,
+    sequence: u64,
+    /// Ticks sent that the server has not applied yet, as far as we know.
+    pending: VecDeque<(u64, Tick)>,
+    /// Tiles broken that the server may not have broken yet, each with the
+    /// first tick that carried it.
+    breaks: Vec<(u64, [isize; 3])>,
+    /// Block changes applied so far.
+    known: usize,
+    /// Every other client as of the last snapshot.
+    pub others: Vec<Remote>,
+}
+
+impl Client {
+    /// Joins the server at `address` and loads the world it sends.
+    pub fn connect(address: &str) -> Result<(Client, Terrain)> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no such address"))?;
+        let local = if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+
+        let (id, options) = ask(&socket, "hello", |message| {
+            let mut lines = message.lines();
+            let id = lines.next()?.strip_prefix("welcome ")?.parse().ok()?;
+            Some((id, lines.map(|line| line.to_string()).collect()))
+        })?;
+
+        let mut world = String::new();
+        loop {
+            let offset = world.len();
+            let (total, part) = ask(&socket, &format!("world {}", offset), |message| {
+                let (header, part) = message.split_once('\n')?;
+                match header.split_whitespace().collect::<Vec<_>>().as_slice() {
+                    ["world", first, total] if first.parse() == Ok(offset) => {
+                        Some((total.parse::<usize>().ok()?, part.to_string()))
+                    }
+                    _ => None,
+                }
+            })?;
+            world += &part;
+            if world.len() >= total || part.is_empty() {
+                break;
+            }
+        }
+        let terrain = world::parse(&world, false, Style::Blocky)?;
+
+        socket.set_nonblocking(true)?;
+        let client = Client {
+            socket,
+            id,
+            options,
+            sequence: 0,
+            pending: VecDeque::new(),
+            breaks: Vec::new(),
+            known: 0,
+            others: Vec::new(),
+        };
+        Ok((client, terrain))
+    }
+
+    /// Sends a tick of input, which the caller has already stepped with its
+    /// frame time cut to `MAX_DELTA` like the server does. The tick carries
+    /// every tile broken that the server has not yet confirmed.
+    pub fn send(&mut self, tick: &Tick) -> Result<()> {
+        self.sequence += 1;
+        self.pending.push_back((self.sequence, tick.clone()));
+        for &tile in &tick.breaks {
+            self.breaks.push((self.sequence, tile));
+        }
+        let tick = Tick {
+            breaks: self.breaks.iter().map(|&(_, tile)| tile).collect(),
+            ..tick.clone()
+        };
+        let message = format!("input {} {} {}", self.sequence, self.known, tick);
+        self.socket.send(message.as_bytes())?;
+        Ok(())
+    }
+
+    /// Applies everything the server sent since the last call: block changes
+    /// to the terrain, our own plane and player as the server has them with
+    /// the ticks it has not seen yet stepped again on top, and everyone else.
+    pub fn receive(
+        &mut self,
+        terrain: &mut Terrain,
+        plane: &mut Plane,
+        player: &mut Player,
+        player_mode: &mut bool,
+    ) -> Result<()> {
+        let mut buffer = [0; 65536];
+        loop {
+            let length = match self.socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) => return Err(error),
+            };
+            let message = String::from_utf8_lossy(&buffer[..length]);
+            let mut lines = message.lines();
+            let header: Vec<&str> = lines.next().unwrap_or("").split_whitespace().collect();
+            match header.as_slice() {
+                ["snapshot", _, sequence] => {
+                    let sequence: u64 = sequence.parse().unwrap_or(0);
+                    let remotes: Vec<Remote> = lines
+                        .filter_map(|line| {
+                            let words: Vec<&str> = line.split_whitespace().collect();
+                            match words.as_slice() {
+                                ["client", rest @ ..] => Remote::parse(rest),
+                                _ => None,
+                            }
+                        })
+                        .collect();
+                    if let Some(own) = remotes.iter().find(|remote| remote.id == self.id) {
+                        self.reconcile(own, sequence, terrain, plane, player, player_mode);
+                    }
+                    self.others = remotes
+                        .into_iter()
+                        .filter(|remote| remote.id != self.id)
+                        .collect();
+                }
+                ["blocks", first] => {
+                    let first: usize = first.parse().unwrap_or(usize::MAX);
+                    for (index, line) in (first..).zip(lines) {
+                        let values: Vec<isize> = line
+                            .split_whitespace()
+                            .filter_map(|word| word.parse().ok())
+                            .collect();
+                        // Changes arrive in order; anything past a gap comes again.
+                        if index != self.known || values.len() != 4 {
+                            continue;
+                        }
+                        terrain.set(values[0], values[1], values[2], values[3] as u8);
+                        self.known += 1;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn reconcile(
+        &mut self,
+        own: &Remote,
+        sequence: u64,
+        terrain: &Terrain,
+        plane: &mut Plane,
+        player: &mut Player,
+        player_mode: &mut bool,
+    ) {
+        while self
+            .pending
+            .front()
+            .is_some_and(|(pending, _)| *pending <= sequence)
+        {
+            self.pending.pop_front();
+        }
+        // Every tick from the first that carried a tile on has carried it.
+        self.breaks.retain(|&(first, _)| first > sequence);
+        plane.set_transform(own.transform);
+        player.set_motion(own.motion);
+        *player_mode = own.player_mode;
+        for (_, tick) in &self.pending {
+            if tick.toggle {
+                *player_mode = !*player_mode;
+            }
+            replay::step(tick, *player_mode, plane, player, terrain);
+        }
+    }
+}
+
+/// Sends `request` until `accept` takes an answer, asking again a few times
+/// in case the datagrams get lost.
+fn ask<T>(socket: &UdpSocket, request: &str, accept: impl Fn(&str) -> Option<T>) -> Result<T> {
+    let mut buffer = [0; 65536];
+    for _ in 0..ATTEMPTS {
+        socket.send(request.as_bytes())?;
+        let deadline = Instant::now() + ATTEMPT_TIMEOUT;
+        // Snapshots may arrive in between and are skipped.
+        while let Some(left) = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())
+        {
+            socket.set_read_timeout(Some(left))?;
+            match socket.recv(&mut buffer) {
+                Ok(length) => {
+                    if let Some(answer) = accept(&String::from_utf8_lossy(&buffer[..length])) {
+                        return Ok(answer);
+                    }
+                }
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused
+                    ) =>
+                {
+                    break
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+    Err(Error::new(ErrorKind::TimedOut, "the server did not answer"))
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.socket.send(b"bye");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct Game {
+        client: Client,
+        terrain: Terrain,
+        plane: Plane,
+        player: Player,
+        player_mode: bool,
+    }
+
+    impl Game {
+        fn join(address: &str) -> Game {
+            let (client, terrain) = Client::connect(address).unwrap();
+            let plane = Plane::new(terrain.size());
+            let player = Player::new(&plane);
+            Game {
+                client,
+                terrain,
+                plane,
+                player,
+                player_mode: false,
+            }
+        }
+
+        fn step(&mut self, tick: &Tick) {
+            self.client.send(tick).unwrap();
+            self.client
+                .receive(
+                    &mut self.terrain,
+                    &mut self.plane,
+                    &mut self.player,
+                    &mut self.player_mode,
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn remotes_read_back_unless_their_transform_has_no_inverse() {
+        let remote = Remote {
+            id: 2,
+            player_mode: true,
+            transform: crate::plane::initial_transform(16),
+            motion: Motion {
+                position: Vector3::new(1.0, 2.0, 3.0),
+                vel_y: -0.5,
+                on_floor: false,
+                facing: -1.0,
+            },
+        };
+        let parse = |line: &str| {
+            let words: Vec<&str> = line.split_whitespace().skip(1).collect();
+            Remote::parse(&words)
+        };
+        let read = parse(&remote.write()).unwrap();
+        assert_eq!(read.id, 2);
+        assert!(read.player_mode);
+        assert_eq!(read.transform, remote.transform);
+        assert_eq!(read.motion, remote.motion);
+
+        for transform in [Matrix4::zeros(), Matrix4::from_element(f32::NAN)] {
+            let broken = Remote {
+                transform,
+                ..remote.clone()
+            };
+            assert!(parse(&broken.write()).is_none());
+        }
+    }
+
+    #[test]
+    fn clients_share_the_world_and_see_each_other() {
+        // Big enough that the world takes a few datagrams.
+        let size = 32;
+        let world = || Terrain::new(size, true, Style::Blocky);
+        let mut server = Server::bind("127.0.0.1:0", world(), vec!["--wrap".to_string()]).unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let serving = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    server.poll().unwrap();
+                }
+            })
+        };
+
+        let mut first = Game::join(&address);
+        let mut second = Game::join(&address);
+        assert_eq!(first.client.options, ["--wrap"]);
+        let expected = world();
+        for game in [&first, &second] {
+            assert!(game.terrain.wrap());
+            assert_eq!(game.terrain.size(), size);
+            for (x, y, z) in (0..size)
+                .flat_map(|x| (0..size).flat_map(move |y| (0..size).map(move |z| (x, y, z))))
+            {
+                assert_eq!(
+                    game.terrain.get_unsafe(x, y, z),
+                    expected.get_unsafe(x, y, z)
+                );
+            }
+        }
+
+        // Both join and the first client's player lands on the terrain.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let idle = Tick::new(0.016);
+        let sees = |game: &Game, other: &Game| {
+            game.client
+                .others
+                .iter()
+                .any(|remote| remote.id == other.client.id)
+        };
+        let wait = |first: &mut Game, second: &mut Game, done: &dyn Fn(&Game, &Game) -> bool| {
+            while !done(first, second) {
+                first.step(&idle);
+                second.step(&idle);
+                assert!(Instant::now() < deadline, "the clients never caught up");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        wait(&mut first, &mut second, &|first, second| {
+            sees(first, second) && sees(second, first) && first.player.on_floor()
+        });
+
+        // It breaks the tile it stands on, once, and one across the world.
+        let top = |x: isize, z: isize| {
+            let y = (0..size as isize)
+                .rev()
+                .find(|&y| expected.get(x, y, z) != Some(block::AIR))
+                .unwrap();
+            [x, y, z]
+        };
+        let position = first.player.world_position();
+        let near = top(position.x.floor() as isize, position.z.floor() as isize);
+        let far = top(1, 1);
+        assert!(can_break(&first.plane, &first.player, near));
+        assert!(!can_break(&first.plane, &first.player, far));
+        let mut tick = Tick::new(0.016);
+        tick.breaks.extend([near, far]);
+        first.step(&tick);
+
+        let broken =
+            |game: &Game, [x, y, z]: [isize; 3]| game.terrain.get(x, y, z) == Some(block::AIR);
+        wait(&mut first, &mut second, &|first, second| {
+            broken(second, near) && first.client.breaks.is_empty()
+        });
+        for game in [&first, &second] {
+            assert!(broken(game, near));
+            assert!(!broken(game, far));
+        }
+
+        drop((first, second));
+        stop.store(true, Ordering::Relaxed);
+        serving.join().unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+//! Debug overlay drawn with egui on top of both views: frame statistics, the
+//! state of the plane and the player, and switches for debug drawing.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use egui_glium::EguiGlium;
+use glium::{implement_vertex, uniform, Surface, VertexBuffer};
+use nalgebra::base::{Matrix4, Vector3};
+
+use super::assets::Shader;
+use super::error::Result;
+
+/// Frames the frame time is averaged over.
+const FRAMES: usize = 60;
+
+#[derive(Clone, Copy)]
+struct LineVertex {
+    position: [f32; 3],
+}
+
+implement_vertex!(LineVertex, position);
+
+/// What the overlay shows about the world, gathered every frame.
+pub struct Stats {
+    pub vertices: usize,
+    pub lines: usize,
+    pub transform: Matrix4<f32>,
+    pub player: (f32, f32),
+    pub player_world: Vector3<f32>,
+    pub on_floor: bool,
+}
+
+pub struct Overlay {
+    egui: EguiGlium,
+    program: Shader,
+    frame_times: VecDeque<f32>,
+    last_frame: Instant,
+    pub visible: bool,
+    /// Draw the terrain in the 3D view as lines.
+    pub wireframe: bool,
+    /// Outline the plane in the 3D view, which is always done with several views.
+    pub outline: bool,
+    /// Draw the tile boundaries in the 2D view.
+    pub grid: bool,
+}
+
+/// Segments where the planes between tiles cut the square of a plane, in its
+/// own coordinates.
+pub fn grid_lines(transform: &Matrix4<f32>, size: usize) -> Vec<[[f32; 2]; 2]> {
+    let half = size as f32 / 2.0;
+    let mut lines = Vec::new();
+    for axis in 0..3 {
+        let row = transform.row(axis);
+        let (du, dv, offset) = (row[0], row[1], row[3]);
+        for k in 0..=size {
+            // Points of the square's border where `du * u + dv * v + offset = k`.
+            let k = k as f32 - offset;
+            let mut points = Vec::new();
+            for side in [-half, half] {
+                if dv.abs() > f32::EPSILON {
+                    points.push([side, (k - du * side) / dv]);
+                }
+                if du.abs() > f32::EPSILON {
+                    points.push([(k - dv * side) / du, side]);
+                }
+            }
+            points.retain(|p| p[0].abs() <= half + 1e-4 && p[1].abs() <= half + 1e-4);
+            if let Some(&first) = points.first() {
+                let distance = |p: &[f32; 2]| (p[0] - first[0]).powi(2) + (p[1] - first[1]).powi(2);
+                let last = points
+                    .iter()
+                    .copied()
+                    .max_by(|a, b| distance(a).total_cmp(&distance(b)))
+                    .unwrap();
+                if distance(&last) > 1e-6 {
+                    lines.push([first, last]);
+                }
+            }
+        }
+    }
+    lines
+}
+
+impl Overlay {
+    pub fn new(display: &glium::Display) -> Result<Overlay> {
+        Ok(Overlay {
+            egui: EguiGlium::new(display),
+            program: Shader::load(display, "line")?,
+            frame_times: VecDeque::with_capacity(FRAMES),
+            last_frame: Instant::now(),
+            visible: false,
+            wireframe: false,
+            outline: false,
+            grid: false,
+        })
+    }
+
+    pub fn reload(&mut self, display: &glium::Display) {
+        self.program.reload(display);
+    }
+
+    /// Passes a window event on to egui.
+    pub fn on_event(&mut self, event: &glium::glutin::event::WindowEvent<'_>) {
+        self.egui.on_event(event);
+    }
+
+    /// Whether the mouse is over the overlay, so clicks should not reach the game.
+    pub fn wants_pointer(&self) -> bool {
+        self.visible && self.egui.egui_ctx.wants_pointer_input()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_lines<T>(
+        &self,
+        display: &glium::Display,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+        vertices: &[LineVertex],
+        color: [f32; 4],
+    ) where
+        T: Surface,
+    {
+        if vertices.is_empty() {
+            return;
+        }
+        let buffer = VertexBuffer::new(display, vertices).unwrap();
+        target
+            .draw(
+                &buffer,
+                glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+                &self.program,
+                &uniform! {
+                    perspective: perspective,
+                    view: view,
+                    color: color,
+                },
+                params,
+            )
+            .unwrap();
+    }
+
+    /// Draws segments over the 2D view, in the coordinates of the plane.
+    pub fn draw_segments<T>(
+        &self,
+        display: &glium::Display,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        projection: [[f32; 4]; 4],
+        segments: &[[[f32; 2]; 2]],
+        color: [f32; 4],
+    ) where
+        T: Surface,
+    {
+        let vertices: Vec<LineVertex> = segments
+            .iter()
+            .flatten()
+            .map(|&[x, y]| LineVertex {
+                position: [x, y, 0.0],
+            })
+            .collect();
+        let identity = Matrix4::identity().into();
+        self.draw_lines(
+            display, target, params, identity, projection, &vertices, color,
+        );
+    }
+
+    /// Draws the tile boundaries over the 2D view.
+    pub fn draw_grid<T>(
+        &self,
+        display: &glium::Display,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        projection: [[f32; 4]; 4],
+        transform: &Matrix4<f32>,
+        size: usize,
+    ) where
+        T: Surface,
+    {
+        self.draw_segments(
+            display,
+            target,
+            params,
+            projection,
+            &grid_lines(transform, size),
+            [0.0, 0.0, 0.0, 0.35],
+        );
+    }
+
+    /// Draws the border of a plane in the 3D view.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_outline<T>(
+        &self,
+        display: &glium::Display,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+        corners: &[Vector3<f32>],
+        color: [f32; 4],
+    ) where
+        T: Surface,
+    {
+        let vertices: Vec<LineVertex> = (0..corners.len())
+            .flat_map(|i| [corners[i], corners[(i + 1) % corners.len()]])
+            .map(|corner| LineVertex {
+                position: corner.into(),
+            })
+            .collect();
+        self.draw_lines(display, target, params, view, perspective, &vertices, color);
+    }
+
+    /// Draws the edges of a box in the 3D view.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_box<T>(
+        &self,
+        display: &glium::Display,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+        (min, max): ([f32; 3], [f32; 3]),
+        color: [f32; 4],
+    ) where
+        T: Surface,
+    {
+        let corner = |i: usize| {
+            let pick = |axis: usize| {
+                if i >> axis & 1 == 0 {
+                    min[axis]
+                } else {
+                    max[axis]
+                }
+            };
+            LineVertex {
+                position: [pick(0), pick(1), pick(2)],
+            }
+        };
+        // Every pair of corners that differs along a single axis is an edge.
+        let vertices: Vec<LineVertex> = (0..8)
+            .flat_map(|i| {
+                (0..3)
+                    .filter(move |axis| i >> axis & 1 == 0)
+                    .flat_map(move |axis| [corner(i), corner(i | 1 << axis)])
+            })
+            .collect();
+        self.draw_lines(display, target, params, view, perspective, &vertices, color);
+    }
+
+    /// Shows the overlay window over the whole frame. Called once per frame,
+    /// which is also when the frame time is measured.
+    pub fn draw(&mut self, display: &glium::Display, target: &mut glium::Frame, stats: &Stats) {
+        let now = Instant::now();
+        if self.frame_times.len() == FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times
+            .push_back(now.duration_since(self.last_frame).as_secs_f32());
+        self.last_frame = now;
+
+        if !self.visible {
+            return;
+        }
+
+        let frame_time = self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32;
+        let origin = stats.transform.column(3).xyz();
+        let yaw = stats.transform[(0, 2)]
+            .atan2(stats.transform[(0, 0)])
+            .to_degrees();
+        let (wireframe, outline, grid) = (&mut self.wireframe, &mut self.outline, &mut self.grid);
+        let (_, shapes) = self.egui.run(display, |ctx| {
+            egui::Window::new("Debug").show(ctx, |ui| {
+                ui.label(format!(
+                    "{:.0} FPS, {:.2} ms",
+                    1.0 / frame_time.max(f32::EPSILON),
+                    frame_time * 1000.0
+                ));
+                ui.label(format!("Vertices: {}", stats.vertices));
+                ui.label(format!("Lines: {}", stats.lines));
+                ui.separator();
+                ui.label(format!(
+                    "Plane origin: {:.2}, {:.2}, {:.2}",
+                    origin.x, origin.y, origin.z
+                ));
+                ui.label(format!("Plane yaw: {:.1}°", yaw));
+                ui.label(format!(
+                    "Player: {:.2}, {:.2} ({:.2}, {:.2}, {:.2})",
+                    stats.player.0,
+                    stats.player.1,
+                    stats.player_world.x,
+                    stats.player_world.y,
+                    stats.player_world.z
+                ));
+                ui.label(format!("On floor: {}", stats.on_floor));
+                ui.separator();
+                ui.checkbox(wireframe, "Wireframe");
+                ui.checkbox(outline, "Plane outline");
+                ui.checkbox(grid, "Grid in 2D view");
+            });
+        });
+        self.egui.paint(display, target, shapes);
+    }
+}
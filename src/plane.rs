@@ -1,99 +1,275 @@
-use glium::{uniform, IndexBuffer, Program, Surface, VertexBuffer};
-use nalgebra::base::{Matrix4, Vector3};
+use glium::uniforms::Uniforms;
+use glium::{uniform, IndexBuffer, Surface, VertexBuffer};
+use nalgebra::base::{Matrix4, Vector3, Vector4};
+
+use super::assets::Shader;
+use super::error::Result;
+use super::slice::{self, AxisBox, Hyperplane};
+use super::utils::Instance;
+
+/// Transform of a plane through `origin`, turned `yaw` radians about the Y axis.
+pub fn pose(origin: Vector3<f32>, yaw: f32) -> Matrix4<f32> {
+    Matrix4::new_translation(&origin) * Matrix4::new_rotation(Vector3::new(0.0, yaw, 0.0))
+}
+
+/// Transform the plane starts with: upright through the centre of the world,
+/// looking at it diagonally.
+pub fn initial_transform(size: usize) -> Matrix4<f32> {
+    let size = size as f32;
+    let mut transform = Matrix4::identity();
+    transform *= Matrix4::new_rotation(Vector3::new(0.0, std::f32::consts::PI, 0.0));
+    transform *= Matrix4::new_translation(&Vector3::new(-size / 2.0, size / 2.0, -size / 2.0));
+    transform *= Matrix4::new_rotation(Vector3::new(0.0, std::f32::consts::PI / 4.0, 0.0));
+    transform
+}
+
+/// World-space corners of the square a plane shows of a world of the given size.
+pub fn corners(transform: &Matrix4<f32>, size: usize) -> Vec<Vector3<f32>> {
+    let half = size as f32 / 2.0;
+    [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)]
+        .iter()
+        .map(|(x, y)| (transform * Vector4::new(x * half, y * half, 0.0, 1.0)).xyz())
+        .collect()
+}
+
+/// Where a world-space point lies on the square of a plane of a world of the
+/// given size, if it is within half a tile of it.
+pub fn on_plane(transform: &Matrix4<f32>, point: Vector3<f32>, size: usize) -> Option<(f32, f32)> {
+    let half = size as f32 / 2.0;
+    let local = transform.try_inverse()? * point.push(1.0);
+    (local.z.abs() < 0.5 && local.x.abs() <= half && local.y.abs() <= half)
+        .then_some((local.x, local.y))
+}
+
+/// Segment where the square of the plane `other` crosses the square of the
+/// plane of `transform`, in the coordinates of the latter, or `None` where
+/// they do not meet.
+pub fn intersection(
+    transform: &Matrix4<f32>,
+    other: &Matrix4<f32>,
+    size: usize,
+) -> Option<[[f32; 2]; 2]> {
+    let half = size as f32 / 2.0;
+    let normal = other.column(2).xyz();
+    let (x_axis, y_axis) = (transform.column(0).xyz(), transform.column(1).xyz());
+    let offset = other.column(3).xyz() - transform.column(3).xyz();
+
+    // Points of this plane with `a * x + b * y = c` lie in the other one.
+    let (a, b, c) = (normal.dot(&x_axis), normal.dot(&y_axis), normal.dot(&offset));
+    let length = (a * a + b * b).sqrt();
+    if length < 1e-6 {
+        return None;
+    }
+    let start = [a * c / (length * length), b * c / (length * length)];
+    let direction = [-b / length, a / length];
+
+    // Keep the part of the line `start + t * direction` that lies within both
+    // squares, where every coordinate changes linearly with `t`.
+    let world = |[x, y]: [f32; 2]| x_axis * x + y_axis * y - offset;
+    let (other_x, other_y) = (other.column(0).xyz(), other.column(1).xyz());
+    let (from, to) = (world(start), x_axis * direction[0] + y_axis * direction[1]);
+    let coordinates = [
+        (start[0], direction[0]),
+        (start[1], direction[1]),
+        (other_x.dot(&from), other_x.dot(&to)),
+        (other_y.dot(&from), other_y.dot(&to)),
+    ];
+    let (mut low, mut high) = (f32::NEG_INFINITY, f32::INFINITY);
+    for (value, rate) in coordinates {
+        if rate.abs() < 1e-6 {
+            if value.abs() > half {
+                return None;
+            }
+        } else {
+            let (t0, t1) = ((-half - value) / rate, (half - value) / rate);
+            low = low.max(t0.min(t1));
+            high = high.min(t0.max(t1));
+        }
+    }
+    if low >= high {
+        return None;
+    }
+    let point = |t: f32| [start[0] + t * direction[0], start[1] + t * direction[1]];
+    Some([point(low), point(high)])
+}
+
+/// Cross-section of an axis-aligned box with the plane of the given transform,
+/// its corners counter-clockwise in plane coordinates, or `None` where they do
+/// not meet.
+pub fn slice_box(transform: &Matrix4<f32>, min: [f32; 3], max: [f32; 3]) -> Option<Vec<[f32; 2]>> {
+    let column = |i: usize| {
+        let c = transform.column(i);
+        [c[0], c[1], c[2]]
+    };
+    let origin = column(3);
+    let hyperplane = Hyperplane::through(origin, column(2));
+    let polytope = slice::intersect(&AxisBox::new(min, max), &hyperplane);
+    if polytope.vertices.len() < 3 {
+        return None;
+    }
+
+    let mut points = polytope.project(origin, &[column(0), column(1)]);
+    slice::order_convex(&mut points);
+    Some(points)
+}
 
 pub struct Plane {
     transform: Matrix4<f32>,
-    scale: Matrix4<f32>,
+    size: usize,
+}
+
+/// Draws a plane as a square in the 3D view.
+pub struct PlaneView {
     buffer: VertexBuffer<super::utils::Vertex>,
     indices: IndexBuffer<u8>,
-    program: Program,
+    program: Shader,
+    /// Draws the planes of other players.
+    observers: Shader,
 }
 
-impl Plane {
-    pub fn new(display: &glium::Display, size: usize) -> Plane {
-        let vertex_shader_src = std::fs::read_to_string("shaders/plane.vert").unwrap();
-        let fragment_shader_src = std::fs::read_to_string("shaders/plane.frag").unwrap();
+impl PlaneView {
+    pub fn new(display: &glium::Display) -> Result<PlaneView> {
+        let program = Shader::instanced(display, "plane")?;
+        let observers = Shader::instanced(display, "observer")?;
+        let (buffer, indices) = super::utils::make_quad(display)?;
 
-        let program =
-            Program::from_source(display, &vertex_shader_src, &fragment_shader_src, None).unwrap();
-
-        let size = size as f32;
-        let mut transform = Matrix4::identity();
-        transform = transform * Matrix4::new_rotation(Vector3::new(0.0, std::f32::consts::PI, 0.0));
-        transform = transform
-            * Matrix4::new_translation(&Vector3::new(-size / 2.0, size / 2.0, -size / 2.0));
+        Ok(PlaneView {
+            buffer,
+            indices,
+            program,
+            observers,
+        })
+    }
 
-        transform =
-            transform * Matrix4::new_rotation(Vector3::new(0.0, std::f32::consts::PI / 4.0, 0.0));
+    pub fn reload(&mut self, display: &glium::Display) {
+        self.program.reload(display);
+        self.observers.reload(display);
+    }
 
-        let (buffer, indices) = super::utils::make_quad(display);
+    /// Model transform that places the square of a plane of a world of the
+    /// given size.
+    pub fn model(transform: &Matrix4<f32>, size: usize) -> [[f32; 4]; 4] {
+        (transform * Matrix4::new_scaling(size as f32 / 2.0)).into()
+    }
 
-        Plane {
-            transform,
-            scale: Matrix4::new_scaling(size / 2.0),
-            buffer,
-            indices, 
-            program,
-        }
+    /// Draws translucent squares, one for every instance, such as the planes
+    /// of other players.
+    pub fn draw_instances(
+        &self,
+        display: &glium::Display,
+        target: &mut glium::Frame,
+        params: &glium::DrawParameters,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+        instances: &[Instance],
+    ) {
+        let uniforms = uniform! {
+            view: view,
+            perspective: perspective,
+        };
+        self.draw_quads(display, target, params, &self.observers, instances, &uniforms);
     }
 
+    /// Draws a plane with an outline where the tiles of its 2D view, rendered
+    /// into `frame`, change.
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &self,
+        display: &glium::Display,
         target: &mut glium::Frame,
         params: &glium::DrawParameters,
+        plane: &Plane,
         view: [[f32; 4]; 4],
         perspective: [[f32; 4]; 4],
         frame: &glium::texture::DepthTexture2d,
         width: f32,
         height: f32,
     ) {
-        let transform: [[f32; 4]; 4] = self.transform().into();
-        let scale: [[f32; 4]; 4] = self.scale.into();
+        let instance = Instance {
+            model: PlaneView::model(&plane.transform(), plane.size),
+            color: [1.0; 4],
+        };
+        let uniforms = uniform! {
+            view: view,
+            perspective: perspective,
+            frame: frame,
+            width: width,
+            height: height,
+        };
+        self.draw_quads(display, target, params, &self.program, &[instance], &uniforms);
+    }
+
+    fn draw_quads<U: Uniforms>(
+        &self,
+        display: &glium::Display,
+        target: &mut glium::Frame,
+        params: &glium::DrawParameters,
+        program: &Shader,
+        instances: &[Instance],
+        uniforms: &U,
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+        let instances = VertexBuffer::new(display, instances).unwrap();
         target
             .draw(
-                &self.buffer,
+                (&self.buffer, instances.per_instance().unwrap()),
                 &self.indices,
-                &self.program,
-                &uniform! {
-                    transform: transform,
-                    view: view,
-                    perspective: perspective,
-                    scale: scale,
-                    frame: frame,
-                    width: width,
-                    height: height,
-                },
+                program,
+                uniforms,
                 params,
             )
             .unwrap();
     }
+}
+
+impl Plane {
+    pub fn new(world_size: usize) -> Plane {
+        Plane {
+            transform: initial_transform(world_size),
+            size: world_size,
+        }
+    }
 
     pub fn strafe_x(&mut self, delta_time: f32, sign: bool) {
         let sign = if sign { 1.0 } else { -1.0 };
-        self.transform =
-            self.transform * Matrix4::new_translation(&Vector3::new(delta_time * sign * 5.0, 0.0, 0.0));
+        self.transform *= Matrix4::new_translation(&Vector3::new(delta_time * sign * 5.0, 0.0, 0.0));
     }
 
     pub fn strafe_z(&mut self, delta_time: f32, sign: bool) {
         let sign = if sign { 1.0 } else { -1.0 };
-        self.transform =
-            self.transform * Matrix4::new_translation(&Vector3::new(0.0, 0.0, delta_time * sign * 5.0));
+        self.transform *= Matrix4::new_translation(&Vector3::new(0.0, 0.0, delta_time * sign * 5.0));
     }
 
     pub fn rotate(&mut self, delta_time: f32, sign: bool) {
         let sign = if sign { 1.0 } else { -1.0 };
-        self.transform = self.transform
-            * Matrix4::new_rotation(Vector3::new(0.0, std::f32::consts::PI / 4.0 * sign * delta_time, 0.0));
+        self.transform *=
+            Matrix4::new_rotation(Vector3::new(0.0, std::f32::consts::PI / 4.0 * sign * delta_time, 0.0));
+    }
+
+    /// Shifts the plane back by whole world sizes once its origin leaves the
+    /// cube, so it stays in view while moving through a periodic world. Returns
+    /// how far it was moved.
+    pub fn wrap_around(&mut self, size: usize) -> Vector3<f32> {
+        let size = size as f32;
+        let x = self.transform[(0, 3)];
+        let z = self.transform[(2, 3)];
+        self.transform[(0, 3)] = x.rem_euclid(size);
+        self.transform[(2, 3)] = z.rem_euclid(size);
+        Vector3::new(self.transform[(0, 3)] - x, 0.0, self.transform[(2, 3)] - z)
+    }
+
+    /// World-space corners of the visible plane quad.
+    pub fn corners(&self) -> Vec<Vector3<f32>> {
+        corners(&self.transform, self.size)
     }
 
     pub fn transform(&self) -> Matrix4<f32> {
-        let r31 = self.transform[4 * 0 + 2];
-        let r32 = self.transform[4 * 1 + 2];
-        let r33 = self.transform[4 * 2 + 2];
-        let mut angle = f32::atan2(-r31, f32::sqrt(r32 * r32 + r33 * r33));
-        angle = angle.abs() % (std::f32::consts::PI / 2.0);
-        angle = f32::min(std::f32::consts::PI / 2.0 - angle, angle);
-        let scale = 1.0 / angle.cos();
-        self.transform //* Matrix4::new_nonuniform_scaling(&Vector3::new(scale, 1.0, scale))
+        self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Matrix4<f32>) {
+        self.transform = transform;
     }
 }
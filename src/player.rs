@@ -1,82 +1,131 @@
-use glium::{uniform, IndexBuffer, Program, Surface, Texture2d, VertexBuffer};
+use glium::{uniform, IndexBuffer, Surface, VertexBuffer};
 use nalgebra::base::{Matrix4, Vector3, Vector4};
 
+use super::assets::{Shader, Texture};
+use super::error::Result;
 use super::plane::Plane;
 use super::terrain::Terrain;
+use super::utils::Instance;
+
+/// Everything about the player that changes as it moves, which the server
+/// sends to keep clients in step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Motion {
+    pub position: Vector3<f32>,
+    pub vel_y: f32,
+    pub on_floor: bool,
+    pub facing: f32,
+}
 
 pub struct Player {
-    scale: Matrix4<f32>,
-    buffer: VertexBuffer<super::utils::Vertex>,
-    indices: IndexBuffer<u8>,
-    texture: Texture2d,
-    program: Program,
-    x: f32,
-    y: f32,
+    /// Centre of the player in world space, kept on the plane by `follow`.
+    position: Vector3<f32>,
     vel_y: f32,
     on_floor: bool,
     width: f32,
+    /// Direction along the plane's X axis the player last walked in.
+    facing: f32,
 }
 
-impl Player {
-    pub fn new(display: &glium::Display) -> Player {
-        let (buffer, indices) = super::utils::make_quad(display);
-
-        let vertex_shader_src = std::fs::read_to_string("shaders/player.vert").unwrap();
-        let fragment_shader_src = std::fs::read_to_string("shaders/player.frag").unwrap();
-
-        let program =
-            Program::from_source(display, &vertex_shader_src, &fragment_shader_src, None).unwrap();
+/// Draws the player's sprite, in the 2D view or on the plane in the 3D view.
+pub struct PlayerView {
+    scale: Matrix4<f32>,
+    buffer: VertexBuffer<super::utils::Vertex>,
+    indices: IndexBuffer<u8>,
+    texture: Texture,
+    program: Shader,
+}
 
-        let image = image::io::Reader::open("assets/character.png").unwrap().decode().unwrap().to_rgba8();
-        let image_dimensions = image.dimensions();
-        let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), image_dimensions);
-        let texture = glium::texture::Texture2d::new(display, image).unwrap();
+impl PlayerView {
+    pub fn new(display: &glium::Display) -> Result<PlayerView> {
+        let (buffer, indices) = super::utils::make_quad(display)?;
+        let program = Shader::instanced(display, "player")?;
+        let texture = Texture::load(display, "character.png")?;
 
-        Player {
+        Ok(PlayerView {
             scale: Matrix4::new_scaling(0.5),
             buffer,
             indices,
             program,
             texture,
-            x: 0.0,
-            y: 0.5,
-            on_floor: false,
-            vel_y: 0.0,
-            width: 8.0 / 16.0,
-        }
+        })
     }
 
-    pub fn draw(
+    /// Picks up changes to the shaders and the character sprite.
+    pub fn reload(&mut self, display: &glium::Display) {
+        self.program.reload(display);
+        self.texture.reload(display);
+    }
+
+    /// Model transform of a player at a point of a plane.
+    pub fn model(&self, transform: &Matrix4<f32>, (x, y): (f32, f32)) -> [[f32; 4]; 4] {
+        (transform * Matrix4::new_translation(&Vector3::new(x, y, 0.0)) * self.scale).into()
+    }
+
+    /// Draws a sprite for every instance, tinted by its colour, such as the
+    /// players of others.
+    pub fn draw_instances(
         &self,
+        display: &glium::Display,
         target: &mut glium::Frame,
         params: &glium::DrawParameters,
         view: [[f32; 4]; 4],
         perspective: [[f32; 4]; 4],
-        transform: [[f32; 4]; 4],
+        instances: &[Instance],
     ) {
-        let translate: [[f32; 4]; 4] =
-            Matrix4::new_translation(&Vector3::new(self.x, self.y, 0.0)).into();
-        let scale: [[f32; 4]; 4] = self.scale.into();
+        if instances.is_empty() {
+            return;
+        }
+        let instances = VertexBuffer::new(display, instances).unwrap();
         target
             .draw(
-                &self.buffer,
+                (&self.buffer, instances.per_instance().unwrap()),
                 &self.indices,
                 &self.program,
                 &uniform! {
                     view: view,
                     perspective: perspective,
-                    transform: transform,
-                    translate: translate,
-                    scale: scale,
-                    tex: &self.texture,
+                    tex: &*self.texture,
                 },
                 params,
             )
             .unwrap();
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        display: &glium::Display,
+        target: &mut glium::Frame,
+        params: &glium::DrawParameters,
+        player: &Player,
+        plane: &Plane,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+        transform: &Matrix4<f32>,
+    ) {
+        let instance = Instance {
+            model: self.model(transform, player.position(plane)),
+            color: [1.0; 4],
+        };
+        self.draw_instances(display, target, params, view, perspective, &[instance]);
+    }
+}
+
+impl Player {
+    pub fn new(plane: &Plane) -> Player {
+        Player {
+            position: (plane.transform() * Vector4::new(0.0, 0.5, 0.0, 1.0)).xyz(),
+            on_floor: false,
+            vel_y: 0.0,
+            width: 8.0 / 16.0,
+            facing: 1.0,
+        }
+    }
+
     fn intersect(&self, plane: &Plane, terrain: &Terrain) -> bool {
-        let corners = [(self.x - self.width / 2.0, self.y - 0.5), (self.x + self.width / 2.0, self.y - 0.5)];
+        let (x, y) = self.position(plane);
+        let corners = [(x - self.width / 2.0, y - 0.5), (x + self.width / 2.0, y - 0.5)];
         let mut corners = corners
             .iter()
             .map(|(x, y)| plane.transform() * Vector4::new(*x, *y, 0.0, 1.0));
@@ -84,7 +133,7 @@ impl Player {
         let dir = corners.next().unwrap() - origin;
         let mut t = 0.0;
 
-        while t <= 1.0 + std::f32::EPSILON {
+        while t <= 1.0 + f32::EPSILON {
             let pos = origin + t * dir;
             let x = pos.x.floor() as isize;
             let y = pos.y.floor() as isize;
@@ -96,7 +145,7 @@ impl Player {
                 let dt = (((dir.x.signum() + 1.0) / 2.0 - pos.x.fract()) / dir.x)
                     .min(((dir.y.signum() + 1.0) / 2.0 - pos.y.fract()) / dir.y)
                     .min(((dir.z.signum() + 1.0) / 2.0 - pos.z.fract()) / dir.z);
-                t += dt + std::f32::EPSILON;
+                t += dt + f32::EPSILON;
             }
         }
 
@@ -105,7 +154,8 @@ impl Player {
 
     pub fn update(&mut self, delta_time: f32, plane: &Plane, terrain: &Terrain) {
         self.vel_y = (self.vel_y - delta_time * 30.0).max(-20.0);
-        self.y += delta_time * self.vel_y;
+        let (x, y) = self.position(plane);
+        self.place(plane, x, y + delta_time * self.vel_y);
         if delta_time != 0.0 {
             self.on_floor = false;
         }
@@ -113,23 +163,103 @@ impl Player {
         while self.intersect(plane, terrain) {
             self.on_floor = true;
             self.vel_y = 0.0;
-            self.y = (self.y - 0.5).floor() + 1.5;
+            self.step_up(plane);
         }
     }
 
     pub fn walk(&mut self, plane: &Plane, terrain: &Terrain, delta_time: f32, sign: bool) {
         let sign = if sign { 1.0 } else { -1.0 };
-        let delta = delta_time * 3.5 * sign;
-        self.x -= delta;
+        let previous = self.position;
+        let (x, y) = self.position(plane);
+        self.place(plane, x - delta_time * 3.5 * sign, y);
+        self.facing = -sign;
 
         if self.intersect(plane, terrain) {
-            self.x += delta;
+            self.position = previous;
         }
     }
 
+    /// Puts the player at a point of the plane.
+    fn place(&mut self, plane: &Plane, x: f32, y: f32) {
+        self.position = (plane.transform() * Vector4::new(x, y, 0.0, 1.0)).xyz();
+    }
+
+    /// Lifts the feet onto the top of the tile they are in.
+    fn step_up(&mut self, plane: &Plane) {
+        let (x, y) = self.position(plane);
+        self.place(plane, x, (y - 0.5).floor() + 1.5);
+    }
+
+    /// Brings the player back onto the plane after it moved, straight along its
+    /// normal so the player stays where it was within the world. A player that
+    /// ends up in the terrain climbs out if it is buried less than a tile deep;
+    /// otherwise it is left where it was and `false` is returned, so the caller
+    /// can undo the plane motion.
+    pub fn follow(&mut self, plane: &Plane, terrain: &Terrain) -> bool {
+        let previous = self.position;
+        let (x, y) = self.position(plane);
+        self.place(plane, x, y);
+        if !self.intersect(plane, terrain) {
+            return true;
+        }
+
+        self.step_up(plane);
+        if !self.intersect(plane, terrain) {
+            return true;
+        }
+        self.position = previous;
+        false
+    }
+
+    /// Moves the player along with a plane that jumped by `offset`, such as
+    /// when it wraps around a periodic world.
+    pub fn shift(&mut self, offset: Vector3<f32>) {
+        self.position += offset;
+    }
+
     pub fn jump(&mut self) {
         if self.on_floor {
             self.vel_y = 12.0;
         }
     }
+
+    /// Position within the plane.
+    pub fn position(&self, plane: &Plane) -> (f32, f32) {
+        let inverse = plane.transform().try_inverse().unwrap();
+        let local = inverse * self.position.push(1.0);
+        (local.x, local.y)
+    }
+
+    pub fn set_position(&mut self, plane: &Plane, x: f32, y: f32) {
+        self.place(plane, x, y);
+        self.vel_y = 0.0;
+    }
+
+    pub fn world_position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    pub fn on_floor(&self) -> bool {
+        self.on_floor
+    }
+
+    pub fn facing(&self) -> f32 {
+        self.facing
+    }
+
+    pub fn motion(&self) -> Motion {
+        Motion {
+            position: self.position,
+            vel_y: self.vel_y,
+            on_floor: self.on_floor,
+            facing: self.facing,
+        }
+    }
+
+    pub fn set_motion(&mut self, motion: Motion) {
+        self.position = motion.position;
+        self.vel_y = motion.vel_y;
+        self.on_floor = motion.on_floor;
+        self.facing = motion.facing;
+    }
 }
@@ -0,0 +1,665 @@
+//! Recording of the input that moves the plane and the player, tick by tick,
+//! and its playback without a window. Frame times are recorded along with the
+//! keys, so playback goes through exactly the steps of the recorded game.
+//!
+//! ```text
+//! # XSection replay
+//! world --wrap --level levels/door.level
+//! tick 0.016 WA
+//! tick 0 - toggle break 3 4 5
+//! tick 0.017 - attach 1
+//! tick 0.016 - paint 4 2 3 4 5 3 5 5
+//! tick 0.016 - undo
+//! tick 0.02 R
+//! ...
+//! plane 0.70710677 0 -0.70710677 0 ...
+//! player 3.5 8.5 2.25 floor
+//! ```
+//!
+//! The world is generated without randomness, so the options that shape it
+//! stand in for a seed on the `world` line. Every `tick` has its frame time,
+//! the keys held as letters, or `-` for none, and whether Tab switched between
+//! player and plane, blocks were broken, the player moved to another view, the
+//! editor painted tiles with a block or an edit was undone or redone. The
+//! `plane` and `player` lines hold the state the game was closed in, which
+//! playback compares against.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+use glium::glutin::event::VirtualKeyCode;
+use nalgebra::base::{Matrix4, Vector3};
+
+use super::block;
+use super::history::{self, History};
+use super::level::Level;
+use super::plane::Plane;
+use super::player::Player;
+use super::terrain::Terrain;
+use super::views::Views;
+use super::world4d::{Hyperplane, Terrain4};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Jump,
+    TurnLeft,
+    TurnRight,
+    /// Turn the hyperplane of a 4D world within the X–W plane.
+    TurnHyperplaneUp,
+    TurnHyperplaneDown,
+    /// Move the hyperplane of a 4D world along its normal.
+    ShiftHyperplaneUp,
+    ShiftHyperplaneDown,
+}
+
+/// Every action with its letter in a recording and the key bound to it.
+const ACTIONS: [(Action, char, VirtualKeyCode); 11] = [
+    (Action::Forward, 'W', VirtualKeyCode::W),
+    (Action::Back, 'S', VirtualKeyCode::S),
+    (Action::Left, 'A', VirtualKeyCode::A),
+    (Action::Right, 'D', VirtualKeyCode::D),
+    (Action::Jump, 'J', VirtualKeyCode::Space),
+    (Action::TurnLeft, 'Q', VirtualKeyCode::Q),
+    (Action::TurnRight, 'E', VirtualKeyCode::E),
+    (Action::TurnHyperplaneUp, 'R', VirtualKeyCode::R),
+    (Action::TurnHyperplaneDown, 'F', VirtualKeyCode::F),
+    (Action::ShiftHyperplaneUp, 'Z', VirtualKeyCode::Z),
+    (Action::ShiftHyperplaneDown, 'X', VirtualKeyCode::X),
+];
+
+/// Input of one pass through the game loop.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tick {
+    pub delta_time: f32,
+    pub actions: Vec<Action>,
+    /// Tab switched between moving the player and moving the plane.
+    pub toggle: bool,
+    /// Tiles broken with the mouse.
+    pub breaks: Vec<[isize; 3]>,
+    /// View the player was attached to.
+    pub attach: Option<usize>,
+    /// Block the editor painted and every tile it painted with it.
+    pub paint: Option<(u8, Vec<[isize; 3]>)>,
+    /// Ctrl+Z undid the last edit.
+    pub undo: bool,
+    /// Ctrl+Y redid the last edit undone.
+    pub redo: bool,
+}
+
+impl Tick {
+    pub fn new(delta_time: f32) -> Tick {
+        Tick {
+            delta_time,
+            ..Default::default()
+        }
+    }
+
+    /// Records the actions whose keys are held.
+    pub fn hold(&mut self, keys_held: &HashSet<VirtualKeyCode>) {
+        self.actions = ACTIONS
+            .iter()
+            .filter(|(_, _, key)| keys_held.contains(key))
+            .map(|&(action, _, _)| action)
+            .collect();
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.actions.contains(&action)
+    }
+
+    /// Reads a tick from the words after `tick`, naming line `number` in errors.
+    pub fn parse(words: &[&str], number: usize) -> Result<Tick> {
+        let delta_time = words
+            .first()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| invalid(number, "expected a frame time"))?;
+        let mut tick = Tick::new(delta_time);
+        for letter in words.get(1).copied().unwrap_or("-").chars() {
+            match ACTIONS.iter().find(|(_, l, _)| *l == letter) {
+                Some(&(action, _, _)) => tick.actions.push(action),
+                None if letter == '-' => (),
+                None => return Err(invalid(number, &format!("unknown action '{}'", letter))),
+            }
+        }
+
+        let mut rest = words.iter().skip(2);
+        while let Some(&word) = rest.next() {
+            match word {
+                "toggle" => tick.toggle = true,
+                "attach" => {
+                    let view = rest
+                        .next()
+                        .and_then(|word| word.parse().ok())
+                        .ok_or_else(|| invalid(number, "expected attach view"))?;
+                    tick.attach = Some(view);
+                }
+                "break" => {
+                    let mut tile = [0; 3];
+                    for c in tile.iter_mut() {
+                        *c = rest
+                            .next()
+                            .and_then(|word| word.parse().ok())
+                            .ok_or_else(|| invalid(number, "expected break x y z"))?;
+                    }
+                    tick.breaks.push(tile);
+                }
+                "paint" => {
+                    let mut next = || rest.next().and_then(|word| word.parse::<isize>().ok());
+                    let expected = || invalid(number, "expected paint block count x y z ...");
+                    let value = next()
+                        .and_then(|value| u8::try_from(value).ok())
+                        .ok_or_else(expected)?;
+                    let count = next().ok_or_else(expected)?;
+                    let mut tiles = Vec::new();
+                    for _ in 0..count {
+                        tiles.push([
+                            next().ok_or_else(expected)?,
+                            next().ok_or_else(expected)?,
+                            next().ok_or_else(expected)?,
+                        ]);
+                    }
+                    tick.paint = Some((value, tiles));
+                }
+                "undo" => tick.undo = true,
+                "redo" => tick.redo = true,
+                _ => return Err(invalid(number, &format!("cannot parse '{}'", word))),
+            }
+        }
+        Ok(tick)
+    }
+}
+
+impl fmt::Display for Tick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letters: String = ACTIONS
+            .iter()
+            .filter(|(action, _, _)| self.held(*action))
+            .map(|&(_, letter, _)| letter)
+            .collect();
+        let letters = if letters.is_empty() { "-" } else { &letters };
+        write!(f, "tick {} {}", self.delta_time, letters)?;
+        if self.toggle {
+            write!(f, " toggle")?;
+        }
+        for [x, y, z] in &self.breaks {
+            write!(f, " break {} {} {}", x, y, z)?;
+        }
+        if let Some(view) = self.attach {
+            write!(f, " attach {}", view)?;
+        }
+        if let Some((value, tiles)) = &self.paint {
+            write!(f, " paint {} {}", value, tiles.len())?;
+            for [x, y, z] in tiles {
+                write!(f, " {} {} {}", x, y, z)?;
+            }
+        }
+        if self.undo {
+            write!(f, " undo")?;
+        }
+        if self.redo {
+            write!(f, " redo")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the plane and the player ended up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub transform: Matrix4<f32>,
+    pub position: Vector3<f32>,
+    pub on_floor: bool,
+}
+
+impl State {
+    pub fn of(plane: &Plane, player: &Player) -> State {
+        State {
+            transform: plane.transform(),
+            position: player.world_position(),
+            on_floor: player.on_floor(),
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let transform: Vec<String> = self.transform.iter().map(|v| v.to_string()).collect();
+        writeln!(f, "plane {}", transform.join(" "))?;
+        write!(
+            f,
+            "player {} {} {} {}",
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            if self.on_floor { "floor" } else { "air" }
+        )
+    }
+}
+
+/// Moves the plane and the player through one tick. The game and playback
+/// both go through here, so a recording plays back the way it was played.
+pub fn step(
+    tick: &Tick,
+    player_mode: bool,
+    plane: &mut Plane,
+    player: &mut Player,
+    terrain: &Terrain,
+) {
+    let delta_time = tick.delta_time;
+    let previous_transform = plane.transform();
+    if tick.held(Action::Forward) && !player_mode {
+        plane.strafe_x(delta_time, true);
+    }
+    if tick.held(Action::Back) && !player_mode {
+        plane.strafe_x(delta_time, false);
+    }
+    if tick.held(Action::Left) {
+        if player_mode {
+            player.walk(plane, terrain, delta_time, true);
+        } else {
+            plane.strafe_z(delta_time, true);
+        }
+    }
+    if tick.held(Action::Right) {
+        if player_mode {
+            player.walk(plane, terrain, delta_time, false);
+        } else {
+            plane.strafe_z(delta_time, false);
+        }
+    }
+    if tick.held(Action::Jump) && player_mode {
+        player.jump();
+    }
+    if tick.held(Action::TurnLeft) {
+        plane.rotate(delta_time, true);
+    }
+    if tick.held(Action::TurnRight) {
+        plane.rotate(delta_time, false);
+    }
+
+    // The player stays put in the world while the plane moves, and the
+    // plane does not move where that would bury the player.
+    if !player.follow(plane, terrain) {
+        plane.set_transform(previous_transform);
+        player.follow(plane, terrain);
+    }
+    if terrain.wrap() {
+        let offset = plane.wrap_around(terrain.size());
+        player.shift(offset);
+    }
+    player.update(delta_time, plane, terrain);
+}
+
+/// Makes the edits of a tick: breaks, a stroke of the editor, undo and redo.
+/// Playback goes through here, while the game makes them as the mouse and
+/// keys ask, in the same way.
+pub fn edit(tick: &Tick, terrain: &mut Terrain, history: &mut History) {
+    for &tile in &tick.breaks {
+        history.set(terrain, tile, block::AIR);
+    }
+    if let Some((value, tiles)) = &tick.paint {
+        history.begin();
+        for &tile in tiles {
+            history.set(terrain, tile, *value);
+        }
+        history.commit();
+    }
+    if tick.undo {
+        history.undo(terrain);
+    }
+    if tick.redo {
+        history.redo(terrain);
+    }
+}
+
+/// Moves the hyperplane of a 4D world through one tick. Once it cuts other
+/// voxels than the `slice` the terrain holds, the new slice is loaded and
+/// `true` returned.
+pub fn step_hyperplane(
+    tick: &Tick,
+    world: &Terrain4,
+    hyperplane: &mut Hyperplane,
+    slice: &mut Vec<u8>,
+    terrain: &mut Terrain,
+) -> bool {
+    let delta_time = tick.delta_time;
+    if tick.held(Action::TurnHyperplaneUp) {
+        hyperplane.rotate(delta_time, true);
+    } else if tick.held(Action::TurnHyperplaneDown) {
+        hyperplane.rotate(delta_time, false);
+    } else if tick.held(Action::ShiftHyperplaneUp) {
+        hyperplane.shift(delta_time, true);
+    } else if tick.held(Action::ShiftHyperplaneDown) {
+        hyperplane.shift(delta_time, false);
+    } else {
+        return false;
+    }
+    // The terrain is only replaced, which relights and rebuilds all of it,
+    // once the hyperplane has moved on to other voxels.
+    let tiles = world.slice(hyperplane);
+    if tiles == *slice {
+        return false;
+    }
+    terrain.load(tiles.clone());
+    *slice = tiles;
+    true
+}
+
+/// Plays a recording back in `terrain`, which is replaced by slices of
+/// `world4d` in 4D mode, with the views named `views` and the level, if any,
+/// and prints what the level says. Returns the state the game ended in.
+pub fn play(
+    replay: &Replay,
+    terrain: &mut Terrain,
+    world4d: Option<(Terrain4, Hyperplane)>,
+    mut level: Option<Level>,
+    views: &[String],
+) -> State {
+    let mut world4d = world4d.map(|(world, hyperplane)| {
+        let slice = world.slice(&hyperplane);
+        terrain.load(slice.clone());
+        (world, hyperplane, slice)
+    });
+    if let Some(level) = &level {
+        level.place(terrain);
+    }
+    let (mut views, mut plane) = Views::new(views, terrain.size());
+    let mut player = Player::new(&plane);
+    let mut player_mode = false;
+    let mut history = History::new(history::LIMIT);
+
+    for tick in &replay.ticks {
+        if tick.toggle {
+            player_mode = !player_mode;
+        }
+        if let Some(view) = tick.attach {
+            views.attach(view, &mut plane, &mut player, terrain);
+        }
+        edit(tick, terrain, &mut history);
+        if let Some((world, hyperplane, slice)) = &mut world4d {
+            if step_hyperplane(tick, world, hyperplane, slice, terrain) {
+                history.clear();
+                if let Some(level) = &level {
+                    level.place(terrain);
+                }
+            }
+        }
+        step(tick, player_mode, &mut plane, &mut player, terrain);
+        if let Some(level) = &mut level {
+            for message in level.update(terrain, &plane.transform(), player.world_position()) {
+                println!("{}", message);
+            }
+        }
+    }
+    State::of(&plane, &player)
+}
+
+fn invalid(number: usize, message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", number + 1, message),
+    )
+}
+
+/// Writes ticks as they are played. Every tick is flushed right away, so a
+/// recording is complete up to a crash.
+pub struct Recorder {
+    file: BufWriter<std::fs::File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, world: &[String]) -> Result<Recorder> {
+        let mut file = BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "# XSection replay")?;
+        writeln!(file, "world {}", world.join(" "))?;
+        Ok(Recorder { file })
+    }
+
+    pub fn record(&mut self, tick: &Tick) -> Result<()> {
+        writeln!(self.file, "{}", tick)?;
+        self.file.flush()
+    }
+
+    /// Ends the recording with the state the game was left in.
+    pub fn finish(mut self, state: &State) -> Result<()> {
+        writeln!(self.file, "{}", state)?;
+        self.file.flush()
+    }
+}
+
+pub struct Replay {
+    /// Options the world was created with.
+    pub world: Vec<String>,
+    pub ticks: Vec<Tick>,
+    /// State the recording ended in, missing if the game did not close cleanly.
+    pub end: Option<State>,
+}
+
+pub fn parse(source: &str) -> Result<Replay> {
+    let mut world = None;
+    let mut ticks = Vec::new();
+    let mut transform = None;
+    let mut end = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let numbers = |values: &[&str]| {
+            values
+                .iter()
+                .map(|word| word.parse::<f32>())
+                .collect::<std::result::Result<Vec<f32>, _>>()
+                .ok()
+        };
+        match words.as_slice() {
+            [] => (),
+            ["world", options @ ..] => {
+                world = Some(options.iter().map(|o| o.to_string()).collect())
+            }
+            ["tick", rest @ ..] => ticks.push(Tick::parse(rest, number)?),
+            ["plane", values @ ..] => match numbers(values) {
+                Some(values) if values.len() == 16 => {
+                    transform = Some(Matrix4::from_column_slice(&values))
+                }
+                _ => return Err(invalid(number, "expected 16 numbers")),
+            },
+            ["player", x, y, z, floor] => {
+                let position = numbers(&[x, y, z])
+                    .ok_or_else(|| invalid(number, "expected player x y z floor|air"))?;
+                let transform =
+                    transform.ok_or_else(|| invalid(number, "expected the plane first"))?;
+                end = Some(State {
+                    transform,
+                    position: Vector3::from_column_slice(&position),
+                    on_floor: *floor == "floor",
+                });
+            }
+            _ => return Err(invalid(number, &format!("cannot parse '{}'", line))),
+        }
+    }
+
+    let world = world.ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing world"))?;
+    Ok(Replay { world, ticks, end })
+}
+
+pub fn load(path: &Path) -> Result<Replay> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block;
+    use crate::smooth::Style;
+
+    /// Tab over to the player, walk left, jump and break a tile, then back on
+    /// the plane turn it and slide it along with the player.
+    const RECORDING: &str = "# XSection replay
+world
+tick 0.016 - toggle
+tick 0.016 A
+tick 0.016 A
+tick 0.016 AJ
+tick 0.016 A
+tick 0.016 - break 5 7 5
+tick 0.5 -
+tick 0.016 D
+tick 0.016 - toggle
+tick 0.25 Q
+tick 0.25 W
+tick 0.016 -
+";
+
+    /// Plays a recording in the generated 16³ world with a single view.
+    fn play_recording(source: &str, world4d: Option<(Terrain4, Hyperplane)>) -> (State, Terrain) {
+        let mut terrain = Terrain::new(16, false, Style::Blocky);
+        let views = ["free".to_string()];
+        let state = play(&parse(source).unwrap(), &mut terrain, world4d, None, &views);
+        (state, terrain)
+    }
+
+    fn assert_same_tiles(actual: &Terrain, expected: &Terrain) {
+        let size = expected.size();
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    assert_eq!(
+                        actual.get_unsafe(x, y, z),
+                        expected.get_unsafe(x, y, z),
+                        "tile {} {} {}",
+                        x,
+                        y,
+                        z
+                    );
+                }
+            }
+        }
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn playback_ends_where_the_game_did() {
+        let (state, _) = play_recording(RECORDING, None);
+        #[rustfmt::skip]
+        let transform = [
+            -0.5555701, 0.0, 0.83146966, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            -0.83146966, 0.0, -0.5555701, 0.0,
+            7.305538, 8.0, 9.039336, 1.0,
+        ];
+        assert_close(state.transform.as_slice(), &transform);
+        assert_close(state.position.as_slice(), &[8.091543, 8.5, 7.8629966]);
+        assert!(state.on_floor);
+    }
+
+    #[test]
+    fn ticks_read_back_as_written() {
+        let tick = Tick {
+            delta_time: 0.0166,
+            actions: vec![
+                Action::Forward,
+                Action::Left,
+                Action::Jump,
+                Action::TurnRight,
+            ],
+            toggle: true,
+            breaks: vec![[3, 4, 5], [-1, 0, 12]],
+            attach: Some(2),
+            paint: Some((block::STONE, vec![[1, 2, 3], [1, 2, 4]])),
+            undo: true,
+            redo: true,
+        };
+        let line = tick.to_string();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        assert_eq!(words[0], "tick");
+        assert_eq!(Tick::parse(&words[1..], 0).unwrap(), tick);
+
+        let idle = Tick::new(0.5);
+        assert_eq!(idle.to_string(), "tick 0.5 -");
+        assert_eq!(Tick::parse(&["0.5", "-"], 0).unwrap(), idle);
+    }
+
+    #[test]
+    fn recordings_read_back_with_their_end_state() {
+        let path = std::env::temp_dir().join(format!("xsection-{}.replay", std::process::id()));
+        let world = vec!["--wrap".to_string()];
+        let expected = parse(RECORDING).unwrap();
+        let (end, _) = play_recording(RECORDING, None);
+
+        let mut recorder = Recorder::create(&path, &world).unwrap();
+        for tick in &expected.ticks {
+            recorder.record(tick).unwrap();
+        }
+        recorder.finish(&end).unwrap();
+        let replay = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(replay.world, world);
+        assert_eq!(replay.ticks, expected.ticks);
+        assert_eq!(replay.end, Some(end));
+    }
+
+    #[test]
+    fn edits_play_back_with_undo_and_redo() {
+        let original = Terrain::new(16, false, Style::Blocky);
+        assert_ne!(original.get(5, 3, 5), Some(block::AIR));
+        // Break a tile and paint over two, then undo both and redo the break.
+        let recording = format!(
+            "world
+tick 0.016 - break 5 3 5
+tick 0.016 - paint {} 2 8 12 8 9 12 9
+tick 0.016 - undo
+tick 0.016 - undo
+tick 0.016 - redo
+",
+            block::STONE
+        );
+        let (_, terrain) = play_recording(&recording, None);
+
+        let mut expected = Terrain::new(16, false, Style::Blocky);
+        expected.set(5, 3, 5, block::AIR);
+        assert_same_tiles(&terrain, &expected);
+
+        let (_, painted) = play_recording(&recording.replace("undo", "redo"), None);
+        assert_eq!(painted.get(8, 12, 8), Some(block::STONE));
+        assert_eq!(painted.get(9, 12, 9), Some(block::STONE));
+    }
+
+    #[test]
+    fn hyperplane_moves_load_their_slice() {
+        let world = Terrain4::new(16);
+        let recording = format!(
+            "world --4d\n{}{}",
+            "tick 0.25 R\n".repeat(6),
+            "tick 0.25 X\n".repeat(4)
+        );
+        let (_, terrain) =
+            play_recording(&recording, Some((Terrain4::new(16), Hyperplane::new(16))));
+
+        let mut hyperplane = Hyperplane::new(16);
+        let start = world.slice(&hyperplane);
+        for _ in 0..6 {
+            hyperplane.rotate(0.25, true);
+        }
+        for _ in 0..4 {
+            hyperplane.shift(0.25, false);
+        }
+        let slice = world.slice(&hyperplane);
+        assert_ne!(slice, start);
+        let mut expected = Terrain::new(16, false, Style::Blocky);
+        expected.load(slice);
+        assert_same_tiles(&terrain, &expected);
+    }
+}
@@ -0,0 +1,278 @@
+//! Level scripting with Rhai. A script may define `on_tick(dt)`,
+//! `on_enter_region(name)` and `on_block_broken(x, y, z, block)`, and uses the
+//! functions registered here to read and change the world. Block ids are
+//! available as `block::STONE` and so on.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use nalgebra::base::Vector3;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Module, Scope, AST};
+
+use super::block;
+use super::entity::{self, Appearance, Body, Entities, Entity};
+use super::plane::{self, Plane};
+use super::player::Player;
+use super::terrain::Terrain;
+
+/// Something that happened in the world, passed to the script on the next tick.
+pub enum Event {
+    BlockBroken([isize; 3], u8),
+}
+
+/// Axis-aligned box of world space with a name, reported to the script
+/// whenever the player steps into it.
+struct Region {
+    name: String,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Region {
+    fn contains(&self, point: Vector3<f32>) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] && point[i] <= self.max[i])
+    }
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// Object map bound to `this` in every hook, where scripts keep their state.
+    state: Dynamic,
+    regions: Rc<RefCell<Vec<Region>>>,
+    inside: Vec<bool>,
+    events: Vec<Event>,
+    player: Rc<RefCell<Player>>,
+}
+
+fn to_vector(values: rhai::Array) -> Option<[f32; 3]> {
+    let values: Vec<f32> = values
+        .into_iter()
+        .map(|v| {
+            v.as_float()
+                .ok()
+                .or_else(|| v.as_int().ok().map(|i| i as f64))
+        })
+        .map(|v| v.map(|v| v as f32))
+        .collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+fn to_array(values: &[f32]) -> rhai::Array {
+    values.iter().map(|&v| Dynamic::from(v as f64)).collect()
+}
+
+/// Places a static box, for `spawn_box` with either a float or an integer extent.
+fn spawn_box(entities: &RefCell<Entities>, position: rhai::Array, extent: f32, color: rhai::Array) {
+    if let (Some(position), Some(color)) = (to_vector(position), to_vector(color)) {
+        entities.borrow_mut().spawn(Entity {
+            position: position.into(),
+            extent,
+            appearance: Appearance::Cube(color),
+            body: None,
+            lifetime: None,
+        });
+    }
+}
+
+impl Script {
+    /// Compiles the script and runs its top level, where regions are declared.
+    pub fn load(
+        path: &std::path::Path,
+        terrain: Rc<RefCell<Terrain>>,
+        plane: Rc<RefCell<Plane>>,
+        player: Rc<RefCell<Player>>,
+        entities: Rc<RefCell<Entities>>,
+    ) -> Result<Script, String> {
+        let mut engine = Engine::new();
+        let regions = Rc::new(RefCell::new(Vec::new()));
+
+        let t = terrain.clone();
+        engine.register_fn("get_block", move |x: i64, y: i64, z: i64| {
+            t.borrow()
+                .get(x as isize, y as isize, z as isize)
+                .unwrap_or(block::AIR) as i64
+        });
+        let t = terrain;
+        engine.register_fn("set_block", move |x: i64, y: i64, z: i64, value: i64| {
+            t.borrow_mut()
+                .set(x as isize, y as isize, z as isize, value as u8);
+        });
+
+        let p = plane.clone();
+        engine.register_fn("plane_origin", move || {
+            let origin = p.borrow().transform().column(3).xyz();
+            to_array(origin.as_slice())
+        });
+        let p = plane.clone();
+        engine.register_fn("plane_yaw", move || {
+            let transform = p.borrow().transform();
+            transform[(0, 2)].atan2(transform[(0, 0)]).to_degrees() as f64
+        });
+        let p = plane.clone();
+        engine.register_fn("set_plane", move |origin: rhai::Array, yaw: f64| {
+            if let Some(origin) = to_vector(origin) {
+                let transform = plane::pose(origin.into(), (yaw as f32).to_radians());
+                p.borrow_mut().set_transform(transform);
+            }
+        });
+
+        let (p, q) = (player.clone(), plane.clone());
+        engine.register_fn("player_position", move || {
+            let (x, y) = p.borrow().position(&q.borrow());
+            to_array(&[x, y])
+        });
+        let p = player.clone();
+        engine.register_fn("player_world_position", move || {
+            to_array(p.borrow().world_position().as_slice())
+        });
+        let (p, q) = (player.clone(), plane);
+        engine.register_fn("set_player_position", move |x: f64, y: f64| {
+            p.borrow_mut().set_position(&q.borrow(), x as f32, y as f32);
+        });
+        let p = player.clone();
+        engine.register_fn("player_on_floor", move || p.borrow().on_floor());
+
+        let e = entities.clone();
+        engine.register_fn(
+            "spawn_box",
+            move |position: rhai::Array, extent: f64, color: rhai::Array| {
+                spawn_box(&e, position, extent as f32, color)
+            },
+        );
+        let e = entities.clone();
+        engine.register_fn(
+            "spawn_box",
+            move |position: rhai::Array, extent: i64, color: rhai::Array| {
+                spawn_box(&e, position, extent as f32, color)
+            },
+        );
+        let e = entities;
+        engine.register_fn(
+            "spawn_ball",
+            move |position: rhai::Array, velocity: rhai::Array, color: rhai::Array| {
+                if let (Some(position), Some(velocity), Some(color)) =
+                    (to_vector(position), to_vector(velocity), to_vector(color))
+                {
+                    e.borrow_mut().spawn(Entity {
+                        position: position.into(),
+                        extent: 0.2,
+                        appearance: Appearance::Sprite(color),
+                        body: Some(Body {
+                            velocity: velocity.into(),
+                            gravity: true,
+                            bounce: 0.5,
+                        }),
+                        lifetime: Some(entity::BALL_LIFETIME),
+                    });
+                }
+            },
+        );
+
+        let r = regions.clone();
+        engine.register_fn(
+            "add_region",
+            move |name: &str, min: rhai::Array, max: rhai::Array| {
+                if let (Some(min), Some(max)) = (to_vector(min), to_vector(max)) {
+                    r.borrow_mut().push(Region {
+                        name: name.to_string(),
+                        min,
+                        max,
+                    });
+                }
+            },
+        );
+
+        let mut blocks = Module::new();
+        for (name, value) in [
+            ("AIR", block::AIR),
+            ("DIRT", block::DIRT),
+            ("TORCH", block::TORCH),
+            ("LAVA", block::LAVA),
+            ("GRASS", block::GRASS),
+            ("STONE", block::STONE),
+            ("DOOR", block::DOOR),
+        ] {
+            blocks.set_var(name, value as i64);
+        }
+        engine.register_static_module("block", blocks.into());
+
+        let mut scope = Scope::new();
+        let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Script {
+            engine,
+            ast,
+            scope,
+            state: Dynamic::from(Map::new()),
+            regions,
+            inside: Vec::new(),
+            events: Vec::new(),
+            player,
+        })
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Calls a hook if the script defines it. Errors are reported and otherwise
+    /// ignored, so a broken hook does not end the game.
+    fn call(&mut self, name: &str, args: impl rhai::FuncArgs) {
+        if !self.ast.iter_functions().any(|f| f.name == name) {
+            return;
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(true)
+            .bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            name,
+            args,
+        );
+        if let Err(error) = result {
+            eprintln!("Script error in {}: {}", name, error);
+        }
+    }
+
+    /// Runs the hooks for everything since the last tick and then `on_tick`.
+    /// Must be called while nothing else borrows the world.
+    pub fn tick(&mut self, delta_time: f32) {
+        let position = self.player.borrow().world_position();
+        let entered: Vec<String> = {
+            let regions = self.regions.borrow();
+            self.inside.resize(regions.len(), false);
+            regions
+                .iter()
+                .zip(self.inside.iter_mut())
+                .filter_map(|(region, inside)| {
+                    let was_inside = *inside;
+                    *inside = region.contains(position);
+                    (*inside && !was_inside).then(|| region.name.clone())
+                })
+                .collect()
+        };
+        for name in entered {
+            self.call("on_enter_region", (name,));
+        }
+
+        for event in std::mem::take(&mut self.events) {
+            match event {
+                Event::BlockBroken([x, y, z], value) => self.call(
+                    "on_block_broken",
+                    (x as i64, y as i64, z as i64, value as i64),
+                ),
+            }
+        }
+
+        self.call("on_tick", (delta_time as f64,));
+    }
+}
@@ -0,0 +1,317 @@
+//! Dimension-generic slicing of axis-aligned boxes by hyperplanes, the CPU
+//! counterpart of `xsection.geom` that works for any orientation.
+
+/// Distances below this are treated as lying on the hyperplane.
+const EPSILON: f32 = 1e-5;
+
+/// Axis-aligned box spanning `min` to `max` in N dimensions.
+#[derive(Clone, Copy, Debug)]
+pub struct AxisBox<const N: usize> {
+    pub min: [f32; N],
+    pub max: [f32; N],
+}
+
+/// The (N-1)-dimensional set of points `p` with `normal · p = offset`.
+#[derive(Clone, Copy, Debug)]
+pub struct Hyperplane<const N: usize> {
+    pub normal: [f32; N],
+    pub offset: f32,
+}
+
+/// Convex polytope given by its vertices, in no particular order.
+#[derive(Clone, Debug, Default)]
+pub struct Polytope<const N: usize> {
+    pub vertices: Vec<[f32; N]>,
+}
+
+fn dot<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+impl<const N: usize> AxisBox<N> {
+    pub fn new(min: [f32; N], max: [f32; N]) -> AxisBox<N> {
+        AxisBox { min, max }
+    }
+
+    /// Corner selected by the bits of `index`, bit `i` choosing `max` on axis `i`.
+    pub fn corner(&self, index: usize) -> [f32; N] {
+        let mut corner = self.min;
+        for (axis, c) in corner.iter_mut().enumerate() {
+            if index & (1 << axis) != 0 {
+                *c = self.max[axis];
+            }
+        }
+        corner
+    }
+}
+
+impl<const N: usize> Hyperplane<N> {
+    /// Hyperplane through `point` with the given normal, which need not be unit length.
+    pub fn through(point: [f32; N], normal: [f32; N]) -> Hyperplane<N> {
+        Hyperplane {
+            normal,
+            offset: dot(&normal, &point),
+        }
+    }
+
+    /// Signed distance of a point, scaled by the length of the normal.
+    pub fn distance(&self, point: &[f32; N]) -> f32 {
+        dot(&self.normal, point) - self.offset
+    }
+}
+
+impl<const N: usize> Polytope<N> {
+    /// Coordinates of the vertices along `M` axes through `origin`, such as an
+    /// orthonormal basis of the hyperplane the polytope was cut with.
+    pub fn project<const M: usize>(&self, origin: [f32; N], axes: &[[f32; N]; M]) -> Vec<[f32; M]> {
+        self.vertices
+            .iter()
+            .map(|vertex| {
+                let mut relative = *vertex;
+                for (r, o) in relative.iter_mut().zip(origin.iter()) {
+                    *r -= o;
+                }
+                let mut projected = [0.0; M];
+                for (p, axis) in projected.iter_mut().zip(axes.iter()) {
+                    *p = dot(&relative, axis);
+                }
+                projected
+            })
+            .collect()
+    }
+
+    fn push_unique(&mut self, point: [f32; N]) {
+        let duplicate = self.vertices.iter().any(|vertex| {
+            vertex
+                .iter()
+                .zip(point.iter())
+                .all(|(a, b)| (a - b).abs() < EPSILON)
+        });
+        if !duplicate {
+            self.vertices.push(point);
+        }
+    }
+}
+
+/// Intersects a box with a hyperplane. The result is the convex polytope of
+/// dimension N-1 where they meet, or empty when the hyperplane misses the box.
+/// Its vertices are the box corners on the hyperplane and the points where the
+/// hyperplane crosses box edges.
+pub fn intersect<const N: usize>(bounds: &AxisBox<N>, hyperplane: &Hyperplane<N>) -> Polytope<N> {
+    let corners: Vec<[f32; N]> = (0..1 << N).map(|i| bounds.corner(i)).collect();
+    let distances: Vec<f32> = corners.iter().map(|c| hyperplane.distance(c)).collect();
+    let mut polytope = Polytope {
+        vertices: Vec::new(),
+    };
+
+    for (corner, distance) in corners.iter().zip(distances.iter()) {
+        if distance.abs() < EPSILON {
+            polytope.push_unique(*corner);
+        }
+    }
+
+    // Every edge joins two corners whose indices differ in a single bit.
+    for a in 0..corners.len() {
+        for axis in 0..N {
+            let b = a | (1 << axis);
+            if b == a {
+                continue;
+            }
+            let (da, db) = (distances[a], distances[b]);
+            if (da < -EPSILON && db > EPSILON) || (da > EPSILON && db < -EPSILON) {
+                let t = da / (da - db);
+                let mut point = corners[a];
+                for (p, (ca, cb)) in point
+                    .iter_mut()
+                    .zip(corners[a].iter().zip(corners[b].iter()))
+                {
+                    *p = ca + (cb - ca) * t;
+                }
+                polytope.push_unique(point);
+            }
+        }
+    }
+
+    polytope
+}
+
+/// Sorts the vertices of a convex polygon counter-clockwise around their centroid.
+pub fn order_convex(points: &mut [[f32; 2]]) {
+    if points.is_empty() {
+        return;
+    }
+    let n = points.len() as f32;
+    let cx = points.iter().map(|p| p[0]).sum::<f32>() / n;
+    let cy = points.iter().map(|p| p[1]).sum::<f32>() / n;
+    points.sort_by(|a, b| {
+        let angle_a = (a[1] - cy).atan2(a[0] - cx);
+        let angle_b = (b[1] - cy).atan2(b[0] - cx);
+        angle_a
+            .partial_cmp(&angle_b)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small xorshift generator, so every run checks the same cases.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            min + (max - min) * self.next()
+        }
+    }
+
+    fn random_box<const N: usize>(random: &mut Random) -> AxisBox<N> {
+        let min = [0.0; N].map(|_| random.range(-5.0, 5.0));
+        let mut max = min;
+        for c in max.iter_mut() {
+            *c += random.range(0.5, 5.0);
+        }
+        AxisBox::new(min, max)
+    }
+
+    /// Hyperplane with a unit normal through a random point of the box.
+    fn random_hyperplane<const N: usize>(
+        random: &mut Random,
+        bounds: &AxisBox<N>,
+    ) -> Hyperplane<N> {
+        let mut point = bounds.min;
+        for (axis, c) in point.iter_mut().enumerate() {
+            *c = random.range(bounds.min[axis], bounds.max[axis]);
+        }
+        let mut normal = [0.0; N].map(|_| random.range(-1.0, 1.0));
+        let length = dot(&normal, &normal).sqrt().max(EPSILON);
+        for c in normal.iter_mut() {
+            *c /= length;
+        }
+        Hyperplane::through(point, normal)
+    }
+
+    /// Whether `value` lies on the face of the box at `min` or `max`.
+    fn on_face(value: f32, min: f32, max: f32) -> bool {
+        (value - min).abs() < 1e-3 || (value - max).abs() < 1e-3
+    }
+
+    /// Checks the vertices of random slices: on the hyperplane, within the box
+    /// and each on an edge of the box, which makes every one of them a corner
+    /// of the convex polytope where the two meet.
+    fn check_random_slices<const N: usize>(seed: u64) {
+        let mut random = Random(seed);
+        for _ in 0..500 {
+            let bounds = random_box::<N>(&mut random);
+            let hyperplane = random_hyperplane(&mut random, &bounds);
+            let polytope = intersect(&bounds, &hyperplane);
+            assert!(
+                polytope.vertices.len() >= N,
+                "{:?} {:?}",
+                bounds,
+                hyperplane
+            );
+
+            for vertex in &polytope.vertices {
+                assert!(hyperplane.distance(vertex).abs() < 1e-3);
+                let faces = (0..N)
+                    .filter(|&axis| {
+                        assert!(vertex[axis] > bounds.min[axis] - 1e-3);
+                        assert!(vertex[axis] < bounds.max[axis] + 1e-3);
+                        on_face(vertex[axis], bounds.min[axis], bounds.max[axis])
+                    })
+                    .count();
+                assert!(faces >= N - 1, "{:?} is not on an edge", vertex);
+            }
+        }
+    }
+
+    /// Checks that the slice through the centre across `axis` is the face of
+    /// the box halfway along it.
+    fn check_axis_aligned<const N: usize>(seed: u64) {
+        let mut random = Random(seed);
+        for _ in 0..100 {
+            let bounds = random_box::<N>(&mut random);
+            for axis in 0..N {
+                let mut centre = bounds.min;
+                for (i, c) in centre.iter_mut().enumerate() {
+                    *c = (bounds.min[i] + bounds.max[i]) / 2.0;
+                }
+                let mut normal = [0.0; N];
+                normal[axis] = 1.0;
+                let polytope = intersect(&bounds, &Hyperplane::through(centre, normal));
+
+                assert_eq!(polytope.vertices.len(), 1 << (N - 1));
+                for vertex in &polytope.vertices {
+                    for i in 0..N {
+                        if i == axis {
+                            assert!((vertex[i] - centre[i]).abs() < 1e-4);
+                        } else {
+                            assert!(on_face(vertex[i], bounds.min[i], bounds.max[i]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_slices_are_on_the_hyperplane_and_box_edges() {
+        check_random_slices::<2>(1);
+        check_random_slices::<3>(2);
+        check_random_slices::<4>(3);
+    }
+
+    #[test]
+    fn axis_aligned_slices_through_the_centre_give_a_face() {
+        check_axis_aligned::<2>(4);
+        check_axis_aligned::<3>(5);
+        check_axis_aligned::<4>(6);
+    }
+
+    #[test]
+    fn ordered_slices_of_3d_boxes_are_convex_polygons() {
+        let mut random = Random(7);
+        for _ in 0..500 {
+            let bounds = random_box::<3>(&mut random);
+            let hyperplane = random_hyperplane(&mut random, &bounds);
+            let polytope = intersect(&bounds, &hyperplane);
+
+            // Any two axes perpendicular to the normal and to each other.
+            let n = hyperplane.normal;
+            let helper = if n[0].abs() < 0.9 {
+                [1.0, 0.0, 0.0]
+            } else {
+                [0.0, 1.0, 0.0]
+            };
+            let u = [
+                n[1] * helper[2] - n[2] * helper[1],
+                n[2] * helper[0] - n[0] * helper[2],
+                n[0] * helper[1] - n[1] * helper[0],
+            ];
+            let length = dot(&u, &u).sqrt();
+            let u = u.map(|c| c / length);
+            let v = [
+                n[1] * u[2] - n[2] * u[1],
+                n[2] * u[0] - n[0] * u[2],
+                n[0] * u[1] - n[1] * u[0],
+            ];
+            let mut points = polytope.project(bounds.min, &[u, v]);
+            order_convex(&mut points);
+
+            // Every turn along the ordered outline is to the left.
+            for i in 0..points.len() {
+                let [a, b, c] = [0, 1, 2].map(|j| points[(i + j) % points.len()]);
+                let cross = (b[0] - a[0]) * (c[1] - b[1]) - (b[1] - a[1]) * (c[0] - b[0]);
+                assert!(cross > -1e-4, "{:?} is not convex", points);
+            }
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use glium::{implement_vertex, uniform, Surface, VertexBuffer};
+use nalgebra::base::{Matrix4, Vector4};
+
+use super::assets::Shader;
+use super::atlas::Atlas;
+use super::error::Result;
+use super::plane::Plane;
+use super::sun::Sun;
+use super::terrain::Terrain;
+
+/// Spacing of the grid the cross-section contour is sampled on.
+const CONTOUR_STEP: f32 = 0.5;
+
+/// How a terrain is turned into geometry.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    /// Unit cubes, sliced into boxes by `xsection.geom`.
+    Blocky,
+    /// An isosurface of the density field, sliced into contours on the CPU.
+    Smooth,
+}
+
+/// Density at a lattice point, the corner shared by the eight tiles below it.
+/// Positive inside solid ground, zero on the surface.
+pub fn density(terrain: &Terrain, x: isize, y: isize, z: isize) -> f32 {
+    let mut solid = 0;
+    for dx in -1..=0 {
+        for dy in -1..=0 {
+            for dz in -1..=0 {
+                if terrain.get(x + dx, y + dy, z + dz).unwrap_or(0) != 0 {
+                    solid += 1;
+                }
+            }
+        }
+    }
+    solid as f32 / 8.0 - 0.5
+}
+
+/// Trilinear interpolation of the density at any point.
+pub fn sample(terrain: &Terrain, point: [f32; 3]) -> f32 {
+    let base = point.map(|c| c.floor());
+    let t = [point[0] - base[0], point[1] - base[1], point[2] - base[2]];
+    let (x, y, z) = (base[0] as isize, base[1] as isize, base[2] as isize);
+
+    let mut value = 0.0;
+    for corner in 0..8 {
+        let (dx, dy, dz) = (corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+        let weight = if dx == 1 { t[0] } else { 1.0 - t[0] }
+            * if dy == 1 { t[1] } else { 1.0 - t[1] }
+            * if dz == 1 { t[2] } else { 1.0 - t[2] };
+        value += weight * density(terrain, x + dx as isize, y + dy as isize, z + dz as isize);
+    }
+    value
+}
+
+/// Closest solid tile to a point on the surface, searched first straight behind
+/// it and then among the tiles around it.
+pub fn solid_tile(terrain: &Terrain, point: [f32; 3]) -> Option<([isize; 3], u8)> {
+    let base = point.map(|c| c.floor() as isize);
+    let candidates =
+        std::iter::once([0, 0, 0]).chain((0..27).map(|i| [i % 3 - 1, (i / 3) % 3 - 1, i / 9 - 1]));
+    for offset in candidates {
+        let tile = [
+            base[0] + offset[0],
+            base[1] + offset[1],
+            base[2] + offset[2],
+        ];
+        let value = terrain.get(tile[0], tile[1], tile[2]).unwrap_or(0);
+        if value != 0 {
+            return Some((tile, value));
+        }
+    }
+    None
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], va: f32, vb: f32) -> [f32; 3] {
+    let t = va / (va - vb);
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Pushes a triangle facing away from `inside`, with its unit normal.
+fn push_triangle(
+    triangle: [[f32; 3]; 3],
+    inside: [f32; 3],
+    out: &mut Vec<([[f32; 3]; 3], [f32; 3])>,
+) {
+    let mut normal = cross(sub(triangle[1], triangle[0]), sub(triangle[2], triangle[0]));
+    let length = dot(normal, normal).sqrt();
+    if length < 1e-6 {
+        return;
+    }
+    let centre = [
+        (triangle[0][0] + triangle[1][0] + triangle[2][0]) / 3.0,
+        (triangle[0][1] + triangle[1][1] + triangle[2][1]) / 3.0,
+        (triangle[0][2] + triangle[1][2] + triangle[2][2]) / 3.0,
+    ];
+    normal = normal.map(|n| n / length);
+    if dot(normal, sub(centre, inside)) < 0.0 {
+        normal = normal.map(|n| -n);
+        out.push(([triangle[0], triangle[2], triangle[1]], normal));
+    } else {
+        out.push((triangle, normal));
+    }
+}
+
+/// Marching cubes over one cell, split into six tetrahedra around its main
+/// diagonal so every case reduces to a triangle or a quad without lookup tables
+/// or ambiguous faces. Corner `i` sits at bit 0 for X, bit 1 for Y, bit 2 for Z.
+pub fn march_cube(
+    corners: [[f32; 3]; 8],
+    values: [f32; 8],
+    out: &mut Vec<([[f32; 3]; 3], [f32; 3])>,
+) {
+    const AXES: [[usize; 3]; 6] = [
+        [1, 2, 4],
+        [1, 4, 2],
+        [2, 1, 4],
+        [2, 4, 1],
+        [4, 1, 2],
+        [4, 2, 1],
+    ];
+
+    for axes in AXES.iter() {
+        let tetrahedron = [0, axes[0], axes[0] | axes[1], 7];
+        let (inside, outside): (Vec<usize>, Vec<usize>) =
+            tetrahedron.iter().partition(|&&i| values[i] > 0.0);
+        let centroid = |indices: &[usize]| {
+            let n = indices.len() as f32;
+            let mut c = [0.0; 3];
+            for &i in indices {
+                c = [
+                    c[0] + corners[i][0] / n,
+                    c[1] + corners[i][1] / n,
+                    c[2] + corners[i][2] / n,
+                ];
+            }
+            c
+        };
+        let cut = |a: usize, b: usize| lerp(corners[a], corners[b], values[a], values[b]);
+
+        match (inside.len(), outside.len()) {
+            (1, 3) => {
+                let a = inside[0];
+                let triangle = [cut(a, outside[0]), cut(a, outside[1]), cut(a, outside[2])];
+                push_triangle(triangle, corners[a], out);
+            }
+            (3, 1) => {
+                let a = outside[0];
+                let triangle = [cut(inside[0], a), cut(inside[1], a), cut(inside[2], a)];
+                push_triangle(triangle, centroid(&inside), out);
+            }
+            (2, 2) => {
+                let (a, b) = (inside[0], inside[1]);
+                let (c, d) = (outside[0], outside[1]);
+                let inside = centroid(&[a, b]);
+                push_triangle([cut(a, c), cut(a, d), cut(b, d)], inside, out);
+                push_triangle([cut(a, c), cut(b, d), cut(b, c)], inside, out);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Marching squares over one cell: the polygon covering the part of the square
+/// with positive values, its corners given counter-clockwise.
+pub fn march_square(corners: [[f32; 2]; 4], values: [f32; 4]) -> Vec<[f32; 2]> {
+    let mut polygon = Vec::new();
+    for i in 0..4 {
+        let j = (i + 1) % 4;
+        if values[i] > 0.0 {
+            polygon.push(corners[i]);
+        }
+        if (values[i] > 0.0) != (values[j] > 0.0) {
+            let t = values[i] / (values[i] - values[j]);
+            polygon.push([
+                corners[i][0] + (corners[j][0] - corners[i][0]) * t,
+                corners[i][1] + (corners[j][1] - corners[i][1]) * t,
+            ]);
+        }
+    }
+    polygon
+}
+
+/// Marching squares over the plane with the given transform. Yields each filled
+/// polygon in plane-local coordinates with the solid tile it shows.
+pub fn contour_polygons(
+    terrain: &Terrain,
+    transform: &Matrix4<f32>,
+) -> Vec<(Vec<[f32; 2]>, [isize; 3], u8)> {
+    let half = terrain.size() as f32 / 2.0;
+    let world = |p: [f32; 2]| {
+        let point = transform * Vector4::new(p[0], p[1], 0.0, 1.0);
+        [point.x, point.y, point.z]
+    };
+
+    let steps = (terrain.size() as f32 / CONTOUR_STEP) as usize;
+    let mut polygons = Vec::new();
+    for i in 0..steps {
+        for j in 0..steps {
+            let x = -half + i as f32 * CONTOUR_STEP;
+            let y = -half + j as f32 * CONTOUR_STEP;
+            let corners = [
+                [x, y],
+                [x + CONTOUR_STEP, y],
+                [x + CONTOUR_STEP, y + CONTOUR_STEP],
+                [x, y + CONTOUR_STEP],
+            ];
+            let values = corners.map(|c| sample(terrain, world(c)));
+            let polygon = march_square(corners, values);
+            if polygon.len() < 3 {
+                continue;
+            }
+
+            let n = polygon.len() as f32;
+            let centre = [
+                polygon.iter().map(|p| p[0]).sum::<f32>() / n,
+                polygon.iter().map(|p| p[1]).sum::<f32>() / n,
+            ];
+            if let Some((tile, value)) = solid_tile(terrain, world(centre)) {
+                polygons.push((polygon, tile, value));
+            }
+        }
+    }
+
+    polygons
+}
+
+#[derive(Clone, Copy)]
+pub struct ContourVertex {
+    position: [f32; 2],
+    tex_coord: [f32; 3],
+    uv: [f32; 2],
+    tile: f32,
+    light: [f32; 2],
+}
+
+implement_vertex!(ContourVertex, position, tex_coord, uv, tile, light);
+
+/// The cross-sections of a smooth terrain, filled by marching squares over the
+/// density sampled on the plane of every view.
+pub struct Contour {
+    /// Contour of every view, along with the transform it was sampled with.
+    views: Vec<Option<(Matrix4<f32>, VertexBuffer<ContourVertex>)>>,
+    indices: glium::index::NoIndices,
+    program: Shader,
+}
+
+impl Contour {
+    pub fn new(display: &glium::Display) -> Result<Contour> {
+        Ok(Contour {
+            views: Vec::new(),
+            indices: glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+            program: Shader::load(display, "contour")?,
+        })
+    }
+
+    pub fn reload(&mut self, display: &glium::Display) {
+        self.program.reload(display);
+    }
+
+    /// Forgets every contour, for instance after the terrain changed.
+    pub fn invalidate(&mut self) {
+        self.views.clear();
+    }
+
+    /// Re-samples the contour of view `index` where its plane cuts the terrain,
+    /// unless the plane has not moved since the last time.
+    pub fn update(
+        &mut self,
+        display: &glium::Display,
+        terrain: &Terrain,
+        index: usize,
+        plane: &Plane,
+    ) {
+        let transform = plane.transform();
+        if self.views.len() <= index {
+            self.views.resize_with(index + 1, || None);
+        }
+        if matches!(&self.views[index], Some((sampled, _)) if *sampled == transform) {
+            return;
+        }
+
+        let size = terrain.size() as f32;
+        let mut vertices = Vec::new();
+        for (polygon, tile, value) in contour_polygons(terrain, &transform) {
+            let tex_coord = [
+                (tile[0].rem_euclid(terrain.size() as isize) as f32 + 0.5) / size,
+                (tile[1] as f32 + 0.5) / size,
+                (tile[2].rem_euclid(terrain.size() as isize) as f32 + 0.5) / size,
+            ];
+            let (sky_light, block_light) = terrain.slice_light(tile[0], tile[1], tile[2]);
+
+            for k in 1..polygon.len() - 1 {
+                for p in [polygon[0], polygon[k], polygon[k + 1]] {
+                    vertices.push(ContourVertex {
+                        position: p,
+                        tex_coord,
+                        uv: p,
+                        tile: value as f32,
+                        light: [sky_light, block_light],
+                    });
+                }
+            }
+        }
+
+        self.views[index] = Some((transform, VertexBuffer::new(display, &vertices).unwrap()));
+    }
+
+    /// Draws the contour of view `index` as last updated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw<'a, T>(
+        &self,
+        index: usize,
+        target: &mut T,
+        params: &glium::DrawParameters,
+        projection: [[f32; 4]; 4],
+        volume: glium::uniforms::Sampler<'a, glium::texture::Texture3d>,
+        atlas: &Atlas,
+        sun: &Sun,
+    ) where
+        T: Surface,
+    {
+        let Some(Some((_, buffer))) = self.views.get(index) else {
+            return;
+        };
+        let sun_color: [f32; 3] = sun.color.into();
+        target
+            .draw(
+                buffer,
+                self.indices,
+                &self.program,
+                &uniform! {
+                    projection: projection,
+                    volume: volume,
+                    atlas: atlas.sampler(),
+                    atlas_tiles: atlas.tiles(),
+                    sun_color: sun_color,
+                },
+                params,
+            )
+            .unwrap();
+    }
+}
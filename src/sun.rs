@@ -0,0 +1,22 @@
+use nalgebra::base::Vector3;
+
+/// Directional light shared by the 3D terrain and the cross-section.
+pub struct Sun {
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+}
+
+impl Sun {
+    pub fn new(direction: Vector3<f32>, color: Vector3<f32>) -> Sun {
+        Sun {
+            direction: direction.normalize(),
+            color,
+        }
+    }
+}
+
+impl Default for Sun {
+    fn default() -> Sun {
+        Sun::new(Vector3::new(-1.0, 4.0, -1.0), Vector3::new(1.0, 1.0, 1.0))
+    }
+}
@@ -1,24 +1,54 @@
-use glium::{implement_vertex, index::NoIndices, uniform, Display, Program, Surface, VertexBuffer};
+use glium::{implement_vertex, index::NoIndices, uniform, Display, Surface, VertexBuffer};
 
+use nalgebra::Vector3;
+
+use std::collections::VecDeque;
+
+use super::assets::Shader;
+use super::atlas::Atlas;
+use super::block;
+use super::error::Result;
+use super::smooth::{self, Style};
+use super::sun::Sun;
+use super::volume;
 use super::xsection::Line;
 
 #[derive(Clone, Copy)]
 pub struct Vertex {
-    position: [f32; 3],
-    normal: [f32; 3],
-    tex_coord: [f32; 3],
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coord: [f32; 3],
+    pub uv: [f32; 2],
+    pub tile: f32,
+    pub occlusion: f32,
+    pub sky_light: f32,
+    pub block_light: f32,
 }
 
-implement_vertex!(Vertex, position, normal, tex_coord);
+implement_vertex!(
+    Vertex,
+    position,
+    normal,
+    tex_coord,
+    uv,
+    tile,
+    occlusion,
+    sky_light,
+    block_light
+);
 
 pub struct Terrain {
-    buffer: VertexBuffer<Vertex>,
-    indices: NoIndices,
-    program: Program,
     tiles: Vec<u8>,
+    /// Colours replacing the block palette per tile, such as those of an imported model.
+    colors: Vec<Option<(u8, u8, u8)>>,
+    light: Vec<u8>,
     vertices: Vec<Vertex>,
     lines: Vec<Line>,
+    shadows: Vec<Line>,
     size: usize,
+    wrap: bool,
+    style: Style,
+    dirty: Option<([usize; 3], [usize; 3])>,
 }
 
 static QUAD: [[f32; 2]; 6] = [
@@ -30,8 +60,32 @@ static QUAD: [[f32; 2]; 6] = [
     [1.0, 1.0],
 ];
 
+/// Brightness of a vertex by the number of solid voxels around its corner,
+/// from fully enclosed to fully open.
+static OCCLUSION: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+static DIRECTIONS: [[isize; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+/// The two light levels packed into every byte of `Terrain::light`: sunlight in
+/// the high nibble and light from emitting blocks in the low nibble.
+#[derive(Clone, Copy, PartialEq)]
+enum Channel {
+    Sky,
+    Block,
+}
+
 impl Terrain {
-    pub fn new(display: &Display, size: usize) -> Terrain {
+    /// Creates a `size`³ terrain. With `wrap` set the world is periodic in X and Z,
+    /// so lookups outside the cube fall back onto the opposite side. `style`
+    /// picks between blocky and smooth geometry for the same tiles.
+    pub fn new(size: usize, wrap: bool, style: Style) -> Terrain {
         let mut tiles = Vec::with_capacity(size * size * size);
 
         for x in 0..size {
@@ -40,56 +94,295 @@ impl Terrain {
                     let xx = (x as f32 / 4.0 - 2.0).sin();
                     let zz = (z as f32 / 4.0 - 2.0).sin();
                     if -(xx * xx) * 2.5 - (zz * zz) * 4.5 + size as f32 / 2.0 > y as f32 {
-                        tiles.push(1);
+                        tiles.push(block::DIRT);
                     } else {
-                        tiles.push(0);
+                        tiles.push(block::AIR);
                     }
                 }
             }
         }
 
-        let buffer = VertexBuffer::new(display, &Vec::new()).unwrap();
-        let indices = NoIndices(glium::index::PrimitiveType::TrianglesList);
-
-        let vertex_shader_src = std::fs::read_to_string("shaders/terrain.vert").unwrap();
-        let fragment_shader_src = std::fs::read_to_string("shaders/terrain.frag").unwrap();
+        let mut terrain = Terrain::with_tiles(size, tiles, wrap, style);
+        terrain.paint_layers();
+        terrain.relight();
+        terrain
+    }
 
-        let program =
-            glium::Program::from_source(display, &vertex_shader_src, &fragment_shader_src, None)
-                .unwrap();
+    /// Creates a terrain from existing tiles in storage order, each optionally
+    /// with its own colour.
+    pub fn from_tiles(
+        size: usize,
+        tiles: Vec<u8>,
+        colors: Vec<Option<(u8, u8, u8)>>,
+        wrap: bool,
+        style: Style,
+    ) -> Terrain {
+        assert_eq!(colors.len(), tiles.len());
+        let mut terrain = Terrain::with_tiles(size, tiles, wrap, style);
+        terrain.colors = colors;
+        terrain.relight();
+        terrain
+    }
 
+    fn with_tiles(size: usize, tiles: Vec<u8>, wrap: bool, style: Style) -> Terrain {
+        assert_eq!(tiles.len(), size * size * size);
         Terrain {
-            buffer,
-            indices,
-            program,
+            colors: vec![None; tiles.len()],
             tiles,
+            light: Vec::new(),
             vertices: Vec::new(),
             lines: Vec::new(),
+            shadows: Vec::new(),
             size,
+            wrap,
+            style,
+            dirty: None,
         }
     }
 
-    pub fn draw<'a>(
+    /// Covers every column with a layer of grass over a few tiles of dirt, with
+    /// stone below.
+    fn paint_layers(&mut self) {
+        for x in 0..self.size {
+            for z in 0..self.size {
+                let mut depth = 0;
+                for y in (0..self.size).rev() {
+                    if self.get_unsafe(x, y, z) == block::AIR {
+                        continue;
+                    }
+                    let tile = match depth {
+                        0 => block::GRASS,
+                        1..=3 => block::DIRT,
+                        _ => block::STONE,
+                    };
+                    self.set_unsafe(x, y, z, tile);
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Hollows out a tunnel below the surface, lit by a torch and a pool of lava.
+    pub fn carve_cave(&mut self) {
+        let size = self.size as f32;
+        for x in 0..self.size {
+            for y in 0..self.size {
+                for z in 0..self.size {
+                    let dx = (x as f32 - size / 2.0) / (size * 0.4);
+                    let dy = (y as f32 - size * 0.2) / 1.6;
+                    let dz = (z as f32 - size / 2.0 - (x as f32 / 3.0).sin() * 2.0) / 1.8;
+                    if dx * dx + dy * dy + dz * dz < 1.0 {
+                        self.set_unsafe(x, y, z, block::AIR);
+                    }
+                }
+            }
+        }
+
+        // Place the light sources on the floor along the middle of the tunnel.
+        let floor = (size * 0.2 - 1.6).ceil() as usize;
+        let middle = |x: usize| (size / 2.0 + (x as f32 / 3.0).sin() * 2.0) as usize;
+        let torch = self.size * 3 / 4;
+        self.set_unsafe(torch, floor, middle(torch), block::TORCH);
+        for x in self.size / 4..self.size / 4 + 2 {
+            self.set_unsafe(x, floor, middle(x), block::LAVA);
+        }
+        self.relight();
+    }
+
+    /// World offsets of every periodic copy touched by the given points, such as
+    /// the corners of the plane. Without wrapping only the original copy exists.
+    pub fn periodic_offsets(&self, points: &[Vector3<f32>]) -> Vec<[f32; 3]> {
+        if !self.wrap || points.is_empty() {
+            return vec![[0.0, 0.0, 0.0]];
+        }
+
+        let size = self.size as f32;
+        let (mut min_x, mut max_x) = (0, 0);
+        let (mut min_z, mut max_z) = (0, 0);
+        for point in points {
+            let x = (point.x / size).floor() as isize;
+            let z = (point.z / size).floor() as isize;
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_z = min_z.min(z);
+            max_z = max_z.max(z);
+        }
+
+        let mut offsets = Vec::new();
+        for x in min_x..=max_x {
+            for z in min_z..=max_z {
+                offsets.push([x as f32 * size, 0.0, z as f32 * size]);
+            }
+        }
+
+        offsets
+    }
+
+    /// Maps a world coordinate onto the stored cube, wrapping X and Z when enabled.
+    fn index(&self, x: isize, y: isize, z: isize) -> Option<(usize, usize, usize)> {
+        let size = self.size as isize;
+        let (x, z) = if self.wrap {
+            (x.rem_euclid(size), z.rem_euclid(size))
+        } else {
+            (x, z)
+        };
+
+        if x < 0 || y < 0 || z < 0 || x >= size || y >= size || z >= size {
+            None
+        } else {
+            Some((x as usize, y as usize, z as usize))
+        }
+    }
+
+    fn light_level(&self, (x, y, z): (usize, usize, usize), channel: Channel) -> u8 {
+        let light = self.light[z * self.size * self.size + y * self.size + x];
+        match channel {
+            Channel::Sky => light >> 4,
+            Channel::Block => light & 0xf,
+        }
+    }
+
+    fn set_light_level(&mut self, (x, y, z): (usize, usize, usize), channel: Channel, level: u8) {
+        let light = &mut self.light[z * self.size * self.size + y * self.size + x];
+        *light = match channel {
+            Channel::Sky => (*light & 0xf) | (level << 4),
+            Channel::Block => (*light & 0xf0) | level,
+        };
+    }
+
+    /// Sky and block light levels at a position. Above the world is open sky,
+    /// while beside and below it is dark.
+    pub fn light(&self, x: isize, y: isize, z: isize) -> (u8, u8) {
+        match self.index(x, y, z) {
+            Some(position) => (
+                self.light_level(position, Channel::Sky),
+                self.light_level(position, Channel::Block),
+            ),
+            None if y >= self.size as isize => (block::MAX_LIGHT, 0),
+            None => (0, 0),
+        }
+    }
+
+    fn neighbour(
         &self,
-        target: &mut glium::Frame,
-        params: &glium::DrawParameters,
-        view: [[f32; 4]; 4],
-        perspective: [[f32; 4]; 4],
-        volume: glium::uniforms::Sampler<'a, glium::texture::Texture3d>,
-    ) {
-        target
-            .draw(
-                &self.buffer,
-                &self.indices,
-                &self.program,
-                &uniform! {
-                    view: view,
-                    perspective: perspective,
-                    volume: volume,
-                },
-                params,
-            )
-            .unwrap();
+        (x, y, z): (usize, usize, usize),
+        direction: [isize; 3],
+    ) -> Option<(usize, usize, usize)> {
+        self.index(
+            x as isize + direction[0],
+            y as isize + direction[1],
+            z as isize + direction[2],
+        )
+    }
+
+    /// Recomputes all light from scratch: sunlight falls down every open column
+    /// from the top of the world and emitting blocks seed block light, after
+    /// which both spread through transparent tiles.
+    fn relight(&mut self) {
+        self.light = vec![0; self.tiles.len()];
+
+        let mut sky = VecDeque::new();
+        let mut lights = VecDeque::new();
+        for x in 0..self.size {
+            for z in 0..self.size {
+                for y in (0..self.size).rev() {
+                    if !block::is_transparent(self.get_unsafe(x, y, z)) {
+                        break;
+                    }
+                    self.set_light_level((x, y, z), Channel::Sky, block::MAX_LIGHT);
+                    sky.push_back((x, y, z));
+                }
+                for y in 0..self.size {
+                    let emission = block::emission(self.get_unsafe(x, y, z));
+                    if emission > 0 {
+                        self.set_light_level((x, y, z), Channel::Block, emission);
+                        lights.push_back((x, y, z));
+                    }
+                }
+            }
+        }
+
+        self.propagate(sky, Channel::Sky);
+        self.propagate(lights, Channel::Block);
+    }
+
+    /// Breadth-first spread of light from the queued positions. Light loses a level
+    /// per step, except full sunlight travelling straight down.
+    fn propagate(&mut self, mut queue: VecDeque<(usize, usize, usize)>, channel: Channel) {
+        while let Some(position) = queue.pop_front() {
+            let level = self.light_level(position, channel);
+            for direction in DIRECTIONS.iter() {
+                let neighbour = match self.neighbour(position, *direction) {
+                    Some(neighbour) => neighbour,
+                    None => continue,
+                };
+                let (x, y, z) = neighbour;
+                if !block::is_transparent(self.get_unsafe(x, y, z)) {
+                    continue;
+                }
+
+                let spread =
+                    if channel == Channel::Sky && direction[1] == -1 && level == block::MAX_LIGHT {
+                        level
+                    } else {
+                        level.saturating_sub(1)
+                    };
+                if spread > self.light_level(neighbour, channel) {
+                    self.set_light_level(neighbour, channel, spread);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+    }
+
+    /// Incrementally updates the light around a changed tile. Light that depended
+    /// on the old tile is removed breadth-first, then the surrounding light that is
+    /// still valid, and any new source, spreads back into the cleared region.
+    fn update_light(&mut self, position: (usize, usize, usize)) {
+        for channel in [Channel::Sky, Channel::Block] {
+            let mut removal = VecDeque::new();
+            let mut refill = VecDeque::new();
+            removal.push_back((position, self.light_level(position, channel)));
+            self.set_light_level(position, channel, 0);
+
+            while let Some((current, level)) = removal.pop_front() {
+                for direction in DIRECTIONS.iter() {
+                    let neighbour = match self.neighbour(current, *direction) {
+                        Some(neighbour) => neighbour,
+                        None => continue,
+                    };
+                    let neighbour_level = self.light_level(neighbour, channel);
+                    if neighbour_level == 0 {
+                        continue;
+                    }
+
+                    let sunlit_below =
+                        channel == Channel::Sky && direction[1] == -1 && level == block::MAX_LIGHT;
+                    if neighbour_level < level || sunlit_below {
+                        self.set_light_level(neighbour, channel, 0);
+                        removal.push_back((neighbour, neighbour_level));
+                    } else {
+                        refill.push_back(neighbour);
+                    }
+                }
+            }
+
+            let (x, y, z) = position;
+            let tile = self.get_unsafe(x, y, z);
+            let source = match channel {
+                Channel::Sky if block::is_transparent(tile) && y == self.size - 1 => {
+                    block::MAX_LIGHT
+                }
+                Channel::Sky => 0,
+                Channel::Block => block::emission(tile),
+            };
+            if source > 0 {
+                self.set_light_level(position, channel, source);
+                refill.push_back(position);
+            }
+
+            self.propagate(refill, channel);
+        }
     }
 
     pub fn get_unsafe(&self, x: usize, y: usize, z: usize) -> u8 {
@@ -97,121 +390,303 @@ impl Terrain {
     }
 
     pub fn get(&self, x: isize, y: isize, z: isize) -> Option<u8> {
-        if x < 0
-            || y < 0
-            || z < 0
-            || x >= self.size as isize
-            || y >= self.size as isize
-            || z >= self.size as isize
-        {
-            None
-        } else {
-            Some(self.get_unsafe(x as usize, y as usize, z as usize))
-        }
+        self.index(x, y, z)
+            .map(|(x, y, z)| self.get_unsafe(x, y, z))
     }
 
-    pub fn set_unsafe(&mut self, x: usize, y: usize, z: usize, value: u8) -> u8 {
-        let old = self.tiles[z * self.size * self.size + y * self.size + x];
-        self.tiles[z * self.size * self.size + y * self.size + x] = value;
+    /// Overwrites a tile without touching the light, which must be recomputed by
+    /// the caller. The tile loses any colour of its own.
+    fn set_unsafe(&mut self, x: usize, y: usize, z: usize, value: u8) -> u8 {
+        let index = z * self.size * self.size + y * self.size + x;
+        let old = self.tiles[index];
+        self.tiles[index] = value;
+        self.colors[index] = None;
         old
     }
 
+    /// Colour of a tile when it overrides the palette colour of its block.
+    pub fn color(&self, x: usize, y: usize, z: usize) -> Option<(u8, u8, u8)> {
+        self.colors[z * self.size * self.size + y * self.size + x]
+    }
+
     pub fn set(&mut self, x: isize, y: isize, z: isize, value: u8) -> Option<u8> {
-        if x < 0
-            || y < 0
-            || z < 0
-            || x >= self.size as isize
-            || y >= self.size as isize
-            || z >= self.size as isize
-        {
-            None
-        } else {
-            Some(self.set_unsafe(x as usize, y as usize, z as usize, value))
+        let (x, y, z) = self.index(x, y, z)?;
+        let old = self.set_unsafe(x, y, z, value);
+        if old != value {
+            self.update_light((x, y, z));
+            self.dirty = Some(match self.dirty {
+                Some((min, max)) => (
+                    [min[0].min(x), min[1].min(y), min[2].min(z)],
+                    [max[0].max(x), max[1].max(y), max[2].max(z)],
+                ),
+                None => ([x, y, z], [x, y, z]),
+            });
         }
+        Some(old)
     }
 
-    fn make_primitives(&self, x: usize, y: usize, z: usize) -> (Vec<Vertex>, Vec<Line>) {
-        let have_above = self
-            .get(x as isize, (y + 1) as isize, z as isize)
+    /// Replaces every tile at once, for instance with a fresh slice of a 4D world.
+    pub fn load(&mut self, tiles: Vec<u8>) {
+        assert_eq!(tiles.len(), self.tiles.len());
+        self.tiles = tiles;
+        self.colors.fill(None);
+        self.relight();
+        let max = self.size - 1;
+        self.dirty = Some(([0, 0, 0], [max, max, max]));
+    }
+
+    /// Takes the inclusive bounds of all tiles changed since the last call.
+    pub fn take_dirty(&mut self) -> Option<([usize; 3], [usize; 3])> {
+        self.dirty.take()
+    }
+
+    /// Voxel ambient occlusion for the face vertex at `corner` (local to the voxel),
+    /// sampled from the three neighbours in front of the face touching that corner.
+    fn occlusion(&self, x: usize, y: usize, z: usize, normal: [isize; 3], corner: [f32; 3]) -> f32 {
+        let solid = |offset: [isize; 3]| {
+            self.get(
+                x as isize + normal[0] + offset[0],
+                y as isize + normal[1] + offset[1],
+                z as isize + normal[2] + offset[2],
+            )
             .unwrap_or(0)
-            != 0;
-        let y_offset = if have_above { 0.0 } else { 0.5 };
-        let mut vertices = Vec::new();
-        let lines = vec![
+                != 0
+        };
+
+        let mut sides = [[0; 3]; 2];
+        let mut n = 0;
+        for axis in 0..3 {
+            if normal[axis] == 0 {
+                sides[n][axis] = if corner[axis] > 0.5 { 1 } else { -1 };
+                n += 1;
+            }
+        }
+
+        let side1 = solid(sides[0]);
+        let side2 = solid(sides[1]);
+        let diagonal = solid([
+            sides[0][0] + sides[1][0],
+            sides[0][1] + sides[1][1],
+            sides[0][2] + sides[1][2],
+        ]);
+
+        if side1 && side2 {
+            OCCLUSION[0]
+        } else {
+            OCCLUSION[3 - side1 as usize - side2 as usize - diagonal as usize]
+        }
+    }
+
+    /// Light shown for a tile in the cross-section. Opaque tiles take the
+    /// brightest light falling on any of their faces.
+    pub fn slice_light(&self, x: isize, y: isize, z: isize) -> (f32, f32) {
+        let (mut sky, mut light) = self.light(x, y, z);
+        if !block::is_transparent(self.get(x, y, z).unwrap_or(block::AIR)) {
+            for direction in DIRECTIONS.iter() {
+                let (neighbour_sky, neighbour_light) =
+                    self.light(x + direction[0], y + direction[1], z + direction[2]);
+                sky = sky.max(neighbour_sky);
+                light = light.max(neighbour_light);
+            }
+        }
+
+        (block::brightness(sky), block::brightness(light))
+    }
+
+    /// Volume texture coordinate of a world position, inset along `inward` so it
+    /// samples the texels of the tile it belongs to.
+    fn tex_coord(&self, position: [f32; 3], inward: [f32; 3]) -> [f32; 3] {
+        let inset = 0.5 / volume::RESOLUTION as f32;
+        let size = self.size as f32;
+        [
+            (position[0] + inward[0] * inset) / size,
+            (position[1] + inward[1] * inset) / size,
+            (position[2] + inward[2] * inset) / size,
+        ]
+    }
+
+    /// The box of a tile as sliced by the cross-section.
+    fn make_line(&self, x: usize, y: usize, z: usize) -> Vec<Line> {
+        let (sky_light, block_light) = self.slice_light(x as isize, y as isize, z as isize);
+        let tile = self.get_unsafe(x, y, z) as f32;
+        let min = [x as f32, y as f32, z as f32];
+        let max = [x as f32 + 1.0, y as f32 + 1.0, z as f32 + 1.0];
+        vec![
             Line {
-                position: [x as f32, y as f32, z as f32],
-                tex_coord: [0.0, y_offset, 0.0],
+                position: min,
+                tex_coord: self.tex_coord(min, [1.0, 1.0, 1.0]),
+                tile,
+                sky_light,
+                block_light,
             },
             Line {
-                position: [x as f32 + 1.0, y as f32 + 1.0, z as f32 + 1.0],
-                tex_coord: [1.0, 0.5 + y_offset, 1.0],
+                position: max,
+                tex_coord: self.tex_coord(max, [-1.0, -1.0, -1.0]),
+                tile,
+                sky_light,
+                block_light,
             },
-        ];
-
-        for i in 0..2 {
-            if self
-                .get(x as isize + i * 2 - 1, y as isize, z as isize)
-                .unwrap_or(0)
-                == 0
-            {
-                for j in 0..6 {
-                    let tex_coord = [i as f32, QUAD[j][0] * 0.5 + y_offset, QUAD[j][1]];
-                    let position = [
-                        x as f32 + tex_coord[0],
-                        y as f32 + QUAD[j][0],
-                        z as f32 + tex_coord[2],
-                    ];
-                    vertices.push(Vertex {
-                        position,
-                        normal: [i as f32 * 2.0 - 1.0, 0.0, 0.0],
-                        tex_coord,
-                    });
-                }
+        ]
+    }
+
+    fn make_primitives(&self, x: usize, y: usize, z: usize) -> (Vec<Vertex>, Vec<Line>) {
+        let mut vertices = Vec::new();
+        let lines = self.make_line(x, y, z);
+        let tile = self.get_unsafe(x, y, z) as f32;
+
+        for direction in DIRECTIONS.iter() {
+            let neighbour = (
+                x as isize + direction[0],
+                y as isize + direction[1],
+                z as isize + direction[2],
+            );
+            if self.get(neighbour.0, neighbour.1, neighbour.2).unwrap_or(0) != 0 {
+                continue;
             }
-            if self
-                .get(x as isize, y as isize + i * 2 - 1, z as isize)
-                .unwrap_or(0)
-                == 0
-            {
-                for j in 0..6 {
-                    let tex_coord = [QUAD[j][0], i as f32 * 0.5 + y_offset, QUAD[j][1]];
-                    let position = [
-                        x as f32 + tex_coord[0],
-                        y as f32 + i as f32,
-                        z as f32 + tex_coord[2],
-                    ];
-                    vertices.push(Vertex {
-                        position,
-                        normal: [0.0, i as f32 * 2.0 - 1.0, 0.0],
-                        tex_coord,
-                    });
-                }
+
+            let (sky_light, block_light) = self.light(neighbour.0, neighbour.1, neighbour.2);
+            let (sky_light, block_light) =
+                (block::brightness(sky_light), block::brightness(block_light));
+            let axis = direction.iter().position(|&d| d != 0).unwrap();
+            let side = (direction[axis] + 1) as f32 / 2.0;
+            let normal = direction.map(|d| d as f32);
+            // `QUAD` turns counter-clockwise around +X, -Y and +Z, so the other
+            // faces take its triangles the other way round.
+            let mut quads = QUAD;
+            if (direction[axis] > 0) == (axis == 1) {
+                quads.reverse();
             }
-            if self
-                .get(x as isize, y as isize, z as isize + i * 2 - 1)
-                .unwrap_or(0)
-                == 0
-            {
-                for j in 0..6 {
-                    let tex_coord = [QUAD[j][0], QUAD[j][1] * 0.5 + y_offset, i as f32];
-                    let position = [
-                        x as f32 + tex_coord[0],
-                        y as f32 + QUAD[j][1],
-                        z as f32 + tex_coord[2],
-                    ];
-                    vertices.push(Vertex {
-                        position,
-                        normal: [0.0, 0.0, i as f32 * 2.0 - 1.0],
-                        tex_coord,
-                    });
+
+            for quad in quads.iter() {
+                // The face lies at `side` along its normal and spans the other two axes.
+                let mut tangent = quad.iter();
+                let mut corner = [side; 3];
+                for (i, c) in corner.iter_mut().enumerate() {
+                    if i != axis {
+                        *c = *tangent.next().unwrap();
+                    }
                 }
+                let position = [
+                    x as f32 + corner[0],
+                    y as f32 + corner[1],
+                    z as f32 + corner[2],
+                ];
+                // Keep textures upright on the side faces.
+                let uv = match axis {
+                    0 => [corner[2], corner[1]],
+                    1 => [corner[0], corner[2]],
+                    _ => [corner[0], corner[1]],
+                };
+                vertices.push(Vertex {
+                    position,
+                    normal,
+                    tex_coord: self.tex_coord(position, normal.map(|n| -n)),
+                    uv,
+                    tile,
+                    occlusion: self.occlusion(x, y, z, *direction, corner),
+                    sky_light,
+                    block_light,
+                });
             }
         }
 
         (vertices, lines)
     }
 
-    pub fn rebuild(&mut self, display: &Display) {
+    /// Isosurface of the density field, one marching cubes cell per tile plus a
+    /// border so the surface closes at the edges of the world.
+    fn make_smooth(&self) -> Vec<Vertex> {
+        let mut triangles = Vec::new();
+        let size = self.size as isize;
+        // Cells beyond the edge close off the surface there. A wrapped world
+        // has no edge along X and Z, and its neighbouring copies draw the cells
+        // there already.
+        let (low, high) = if self.wrap { (0, size - 1) } else { (-1, size) };
+        for x in low..=high {
+            for y in -1..=size {
+                for z in low..=high {
+                    let mut corners = [[0.0; 3]; 8];
+                    let mut values = [0.0; 8];
+                    for (i, (corner, value)) in
+                        corners.iter_mut().zip(values.iter_mut()).enumerate()
+                    {
+                        let (cx, cy, cz) = (
+                            x + (i & 1) as isize,
+                            y + ((i >> 1) & 1) as isize,
+                            z + ((i >> 2) & 1) as isize,
+                        );
+                        *corner = [cx as f32, cy as f32, cz as f32];
+                        *value = smooth::density(self, cx, cy, cz);
+                    }
+                    smooth::march_cube(corners, values, &mut triangles);
+                }
+            }
+        }
+
+        let mut vertices = Vec::with_capacity(triangles.len() * 3);
+        for (triangle, normal) in triangles {
+            let centre = [
+                (triangle[0][0] + triangle[1][0] + triangle[2][0]) / 3.0,
+                (triangle[0][1] + triangle[1][1] + triangle[2][1]) / 3.0,
+                (triangle[0][2] + triangle[1][2] + triangle[2][2]) / 3.0,
+            ];
+            let behind = [
+                centre[0] - normal[0] * 0.25,
+                centre[1] - normal[1] * 0.25,
+                centre[2] - normal[2] * 0.25,
+            ];
+            let (tile, value) = match smooth::solid_tile(self, behind) {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let tile_centre = [
+                tile[0].rem_euclid(size) as f32 + 0.5,
+                tile[1] as f32 + 0.5,
+                tile[2].rem_euclid(size) as f32 + 0.5,
+            ];
+            let (sky_light, block_light) = self.light(
+                (centre[0] + normal[0] * 0.5).floor() as isize,
+                (centre[1] + normal[1] * 0.5).floor() as isize,
+                (centre[2] + normal[2] * 0.5).floor() as isize,
+            );
+            let (sky_light, block_light) =
+                (block::brightness(sky_light), block::brightness(block_light));
+            let axis = (0..3)
+                .max_by(|&a, &b| normal[a].abs().partial_cmp(&normal[b].abs()).unwrap())
+                .unwrap();
+
+            for position in triangle {
+                let uv = match axis {
+                    0 => [position[2], position[1]],
+                    1 => [position[0], position[2]],
+                    _ => [position[0], position[1]],
+                };
+                vertices.push(Vertex {
+                    position,
+                    normal,
+                    tex_coord: self.tex_coord(tile_centre, [0.0; 3]),
+                    uv,
+                    tile: value as f32,
+                    occlusion: 1.0,
+                    sky_light,
+                    block_light,
+                });
+            }
+        }
+
+        vertices
+    }
+
+    /// Regenerates the mesh and the cross-section boxes from the tiles.
+    pub fn rebuild(&mut self) {
+        self.vertices.clear();
+        self.lines.clear();
+        self.shadows.clear();
+        if self.style == Style::Smooth {
+            self.vertices = self.make_smooth();
+            return;
+        }
+
         for x in 0..self.size {
             for y in 0..self.size {
                 for z in 0..self.size {
@@ -219,12 +694,13 @@ impl Terrain {
                         let (vertices, lines) = self.make_primitives(x, y, z);
                         self.vertices.extend(vertices);
                         self.lines.extend(lines);
+                    } else if self.light_level((x, y, z), Channel::Sky) < block::MAX_LIGHT {
+                        let shadow = self.make_line(x, y, z);
+                        self.shadows.extend(shadow);
                     }
                 }
             }
         }
-
-        self.buffer = glium::VertexBuffer::new(display, self.vertices()).unwrap();
     }
 
     pub fn vertices(&self) -> &[Vertex] {
@@ -235,7 +711,82 @@ impl Terrain {
         &self.lines
     }
 
+    /// Boxes of the transparent tiles that are not in full sunlight, drawn as a
+    /// darkening over the cross-section.
+    pub fn shadows(&self) -> &[Line] {
+        &self.shadows
+    }
+
     pub fn size(&self) -> usize {
         self.size
     }
+
+    pub fn wrap(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+}
+
+/// The terrain mesh on the GPU, re-uploaded whenever the terrain is rebuilt.
+pub struct TerrainView {
+    buffer: VertexBuffer<Vertex>,
+    indices: NoIndices,
+    program: Shader,
+}
+
+impl TerrainView {
+    pub fn new(display: &Display, terrain: &Terrain) -> Result<TerrainView> {
+        Ok(TerrainView {
+            buffer: VertexBuffer::new(display, terrain.vertices())?,
+            indices: NoIndices(glium::index::PrimitiveType::TrianglesList),
+            program: Shader::load(display, "terrain")?,
+        })
+    }
+
+    pub fn update(&mut self, display: &Display, terrain: &Terrain) {
+        self.buffer = VertexBuffer::new(display, terrain.vertices()).unwrap();
+    }
+
+    pub fn reload(&mut self, display: &Display) {
+        self.program.reload(display);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw<'a>(
+        &self,
+        target: &mut glium::Frame,
+        params: &glium::DrawParameters,
+        view: [[f32; 4]; 4],
+        perspective: [[f32; 4]; 4],
+        volume: glium::uniforms::Sampler<'a, glium::texture::Texture3d>,
+        atlas: &Atlas,
+        offsets: &[[f32; 3]],
+        sun: &Sun,
+    ) {
+        let sun_direction: [f32; 3] = sun.direction.into();
+        let sun_color: [f32; 3] = sun.color.into();
+        for offset in offsets {
+            target
+                .draw(
+                    &self.buffer,
+                    self.indices,
+                    &self.program,
+                    &uniform! {
+                        view: view,
+                        perspective: perspective,
+                        volume: volume,
+                        atlas: atlas.sampler(),
+                        atlas_tiles: atlas.tiles(),
+                        offset: *offset,
+                        sun_direction: sun_direction,
+                        sun_color: sun_color,
+                    },
+                    params,
+                )
+                .unwrap();
+        }
+    }
 }
@@ -1,5 +1,7 @@
 use glium::{implement_vertex, IndexBuffer, VertexBuffer};
 
+use super::error::Result;
+
 #[derive(Clone, Copy)]
 pub struct Vertex {
     position: [f32; 2],
@@ -8,7 +10,16 @@ pub struct Vertex {
 
 implement_vertex!(Vertex, position, tex_coord);
 
-pub fn make_quad(display: &glium::Display) -> (VertexBuffer<Vertex>, IndexBuffer<u8>) {
+/// Placement and colour of one copy of a quad drawn many times in one call.
+#[derive(Clone, Copy)]
+pub struct Instance {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+
+implement_vertex!(Instance, model, color);
+
+pub fn make_quad(display: &glium::Display) -> Result<(VertexBuffer<Vertex>, IndexBuffer<u8>)> {
     let vertices = [
         Vertex {
             position: [-1.0, 1.0],
@@ -29,13 +40,12 @@ pub fn make_quad(display: &glium::Display) -> (VertexBuffer<Vertex>, IndexBuffer
     ];
     let indices = [0, 1, 2, 0, 2, 3];
 
-    let buffer = VertexBuffer::new(display, &vertices).unwrap();
+    let buffer = VertexBuffer::new(display, &vertices)?;
     let indices = IndexBuffer::new(
         display,
         glium::index::PrimitiveType::TrianglesList,
         &indices,
-    )
-    .unwrap();
+    )?;
 
-    (buffer, indices)
+    Ok((buffer, indices))
 }
@@ -0,0 +1,140 @@
+//! Named slice views. Each has a plane of its own, shown in a tile of the 2D
+//! half of the window and outlined in its colour in the 3D view. The player is
+//! attached to one of them at a time, which is the plane that moves.
+
+use nalgebra::base::{Matrix4, Vector3};
+
+use super::plane::{self, Plane};
+use super::player::Player;
+use super::terrain::Terrain;
+
+/// Colours the views are told apart by, in order.
+const COLORS: [[f32; 3]; 6] = [
+    [1.0, 0.9, 0.2],
+    [0.3, 0.9, 0.9],
+    [1.0, 0.5, 0.9],
+    [0.6, 1.0, 0.4],
+    [1.0, 0.6, 0.3],
+    [0.6, 0.6, 1.0],
+];
+
+/// Where a view named `name` starts: `xy` faces along Z and `zy` along X, both
+/// through the centre of the world. Any other name gets the usual diagonal plane.
+pub fn preset(name: &str, size: usize) -> Matrix4<f32> {
+    let center = Vector3::repeat(size as f32 / 2.0);
+    match name {
+        "xy" => plane::pose(center, 0.0),
+        "zy" => plane::pose(center, std::f32::consts::FRAC_PI_2),
+        _ => plane::initial_transform(size),
+    }
+}
+
+/// Square tiles for `count` views, as large as they fit in a grid within `area`.
+pub fn layout(count: usize, area: glium::Rect) -> Vec<glium::Rect> {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let rows = (count as u32).div_ceil(columns).max(1);
+    let side = (area.width / columns).min(area.height / rows);
+    (0..count as u32)
+        .map(|i| glium::Rect {
+            left: area.left + i % columns * side,
+            bottom: area.bottom + area.height - (i / columns + 1) * side,
+            width: side,
+            height: side,
+        })
+        .collect()
+}
+
+/// The tile under a point of the window, measured from the top left as the
+/// cursor is, and the point of that view's plane beneath it.
+pub fn locate(
+    tiles: &[glium::Rect],
+    (x, y): (f32, f32),
+    height: f32,
+    size: usize,
+) -> Option<(usize, (f32, f32))> {
+    let y = height - y;
+    let size = size as f32;
+    tiles.iter().enumerate().find_map(|(index, tile)| {
+        let u = (x - tile.left as f32) / tile.width as f32;
+        let v = (y - tile.bottom as f32) / tile.height as f32;
+        ((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v))
+            .then_some((index, (u * size - size / 2.0, v * size - size / 2.0)))
+    })
+}
+
+pub struct Views {
+    names: Vec<String>,
+    /// Plane of every view, except for the attached one, whose plane is lent
+    /// out to the game while it is attached and whose entry is out of date.
+    planes: Vec<Plane>,
+    attached: usize,
+}
+
+impl Views {
+    /// Creates the views with the given names, returning them along with the
+    /// plane of the first one, which the player starts out attached to.
+    pub fn new(names: &[String], size: usize) -> (Views, Plane) {
+        let plane = |name: &str| {
+            let mut plane = Plane::new(size);
+            plane.set_transform(preset(name, size));
+            plane
+        };
+        let views = Views {
+            names: names.to_vec(),
+            planes: names.iter().map(|name| plane(name)).collect(),
+            attached: 0,
+        };
+        (views, plane(&names[0]))
+    }
+
+    pub fn count(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+
+    pub fn color(&self, index: usize) -> [f32; 3] {
+        COLORS[index % COLORS.len()]
+    }
+
+    pub fn attached(&self) -> usize {
+        self.attached
+    }
+
+    /// Plane of a view, given the plane of the attached one.
+    pub fn plane<'a>(&'a self, index: usize, attached: &'a Plane) -> &'a Plane {
+        if index == self.attached {
+            attached
+        } else {
+            &self.planes[index]
+        }
+    }
+
+    /// Attaches the player to another view, swapping its plane in for the
+    /// attached `plane`. The player moves straight onto the new plane; where
+    /// that would bury it the views stay as they were and `false` is returned.
+    pub fn attach(
+        &mut self,
+        index: usize,
+        plane: &mut Plane,
+        player: &mut Player,
+        terrain: &Terrain,
+    ) -> bool {
+        let previous = self.attached;
+        if index == previous || index >= self.count() {
+            return index == previous;
+        }
+        std::mem::swap(&mut self.planes[previous], plane);
+        std::mem::swap(&mut self.planes[index], plane);
+        if player.follow(plane, terrain) {
+            self.attached = index;
+            true
+        } else {
+            std::mem::swap(&mut self.planes[index], plane);
+            std::mem::swap(&mut self.planes[previous], plane);
+            false
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::texture::{MipmapsOption, Texture3d, UncompressedFloatFormat};
+
+use super::block;
+use super::error::Result;
+use super::terrain::Terrain;
+
+/// Texels along each axis of a single tile.
+pub const RESOLUTION: usize = 4;
+
+/// Colour volume covering the whole terrain, a few texels per tile so every
+/// block gets some noise on top of its palette colour.
+pub struct Volume {
+    texture: Texture3d,
+}
+
+/// Cheap integer hash of a texel position to a value in `[0, 1)`.
+fn noise(x: usize, y: usize, z: usize) -> f32 {
+    let mut h = (x as u32)
+        .wrapping_mul(374_761_393)
+        .wrapping_add((y as u32).wrapping_mul(668_265_263))
+        .wrapping_add((z as u32).wrapping_mul(2_147_483_647));
+    h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+    h ^= h >> 16;
+    (h & 0xffff) as f32 / 65536.0
+}
+
+/// Colour of the texel at the given texel coordinates.
+fn texel(terrain: &Terrain, x: usize, y: usize, z: usize) -> (u8, u8, u8) {
+    let (tx, ty, tz) = (x / RESOLUTION, y / RESOLUTION, z / RESOLUTION);
+    let tile = terrain.get_unsafe(tx, ty, tz);
+    let noise = noise(x, y, z);
+
+    // Grass only covers the top of its tile, the rest of it is dirt.
+    let top = RESOLUTION - 1 - (noise > 0.6) as usize;
+    let tile = if tile == block::GRASS && y % RESOLUTION < top {
+        block::DIRT
+    } else {
+        tile
+    };
+
+    // Tiles with a colour of their own, such as imported voxels, keep it whole.
+    let (r, g, b) = terrain
+        .color(tx, ty, tz)
+        .unwrap_or_else(|| block::color(tile));
+    let shade = 0.85 + 0.3 * noise;
+    let scale = |c: u8| (c as f32 * shade).min(255.0) as u8;
+    (scale(r), scale(g), scale(b))
+}
+
+impl Volume {
+    pub fn new(display: &glium::Display, terrain: &Terrain) -> Result<Volume> {
+        let size = terrain.size() * RESOLUTION;
+        let data: Vec<Vec<Vec<(u8, u8, u8)>>> = (0..size)
+            .map(|z| {
+                (0..size)
+                    .map(|y| (0..size).map(|x| texel(terrain, x, y, z)).collect())
+                    .collect()
+            })
+            .collect();
+
+        let texture = Texture3d::with_format(
+            display,
+            data,
+            UncompressedFloatFormat::U8U8U8,
+            MipmapsOption::NoMipmap,
+        )?;
+
+        Ok(Volume { texture })
+    }
+
+    /// Regenerates the texels of the tiles between `min` and `max` inclusive.
+    pub fn update(
+        &self,
+        display: &glium::Display,
+        terrain: &Terrain,
+        min: [usize; 3],
+        max: [usize; 3],
+    ) {
+        let start = min.map(|c| c * RESOLUTION);
+        let end = max.map(|c| (c + 1) * RESOLUTION);
+
+        let mut data = Vec::new();
+        for z in start[2]..end[2] {
+            for y in start[1]..end[1] {
+                for x in start[0]..end[0] {
+                    data.push(texel(terrain, x, y, z));
+                }
+            }
+        }
+
+        let buffer = PixelBuffer::new_empty(display, data.len());
+        buffer.write(&data);
+        self.texture.main_level().raw_upload_from_pixel_buffer(
+            buffer.as_slice(),
+            start[0] as u32..end[0] as u32,
+            start[1] as u32..end[1] as u32,
+            start[2] as u32..end[2] as u32,
+        );
+    }
+
+    pub fn sampler(&self) -> glium::uniforms::Sampler<'_, Texture3d> {
+        glium::uniforms::Sampler::new(&self.texture)
+            .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+            .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+    }
+}
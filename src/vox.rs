@@ -0,0 +1,300 @@
+//! Loader for MagicaVoxel `.vox` models, turned into terrain tiles whose block
+//! types are picked by colour and whose palette colours feed the volume texture.
+
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use super::block;
+use super::smooth::Style;
+use super::terrain::Terrain;
+
+/// Largest model accepted along any axis. The colour volume holds
+/// `volume::RESOLUTION` texels per tile along each axis, so a 64³ world already
+/// takes a 256³ texture, and MagicaVoxel's largest 256³ models would take
+/// gigabytes.
+pub const MAX_SIZE: usize = 64;
+
+/// A single model from a `.vox` file. MagicaVoxel has Z pointing up.
+pub struct Model {
+    pub size: [usize; 3],
+    /// Position and palette index of every filled voxel.
+    pub voxels: Vec<([usize; 3], u8)>,
+    /// Colour of every palette index, index zero being empty.
+    pub palette: [(u8, u8, u8); 256],
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Little endian `i32` at `offset`, rejecting negative values.
+fn read_u32(data: &[u8], offset: usize) -> Result<usize> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or_else(|| invalid("unexpected end of file"))?;
+    let value = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    usize::try_from(value).map_err(|_| invalid("negative size"))
+}
+
+/// The palette MagicaVoxel uses for files without an `RGBA` chunk: a 6×6×6
+/// colour cube followed by ramps of red, green, blue and grey.
+fn default_palette() -> [(u8, u8, u8); 256] {
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = [(0, 0, 0); 256];
+    for (k, color) in palette[1..216].iter_mut().enumerate() {
+        let level = |i: usize| 255 - 51 * i as u8;
+        *color = (level(k / 36), level((k / 6) % 6), level(k % 6));
+    }
+    for (i, &c) in RAMP.iter().enumerate() {
+        palette[216 + i] = (c, 0, 0);
+        palette[226 + i] = (0, c, 0);
+        palette[236 + i] = (0, 0, c);
+        palette[246 + i] = (c, c, c);
+    }
+    palette
+}
+
+/// Parses the first model of a `.vox` file along with its palette.
+pub fn parse(data: &[u8]) -> Result<Model> {
+    if data.get(0..4) != Some(b"VOX ") {
+        return Err(invalid("not a MagicaVoxel file"));
+    }
+    if data.get(8..12) != Some(b"MAIN") {
+        return Err(invalid("missing MAIN chunk"));
+    }
+
+    let mut size = None;
+    let mut voxels = None;
+    let mut palette = default_palette();
+
+    // The children of MAIN follow its (empty) content, each with a 12 byte header.
+    let mut offset = 20 + read_u32(data, 12)?;
+    while offset + 12 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let content = read_u32(data, offset + 4)?;
+        let children = read_u32(data, offset + 8)?;
+        let start = offset + 12;
+        let chunk = data
+            .get(start..start + content)
+            .ok_or_else(|| invalid("chunk runs past the end of the file"))?;
+
+        match id {
+            b"SIZE" if size.is_none() => {
+                size = Some([
+                    read_u32(chunk, 0)?,
+                    read_u32(chunk, 4)?,
+                    read_u32(chunk, 8)?,
+                ]);
+            }
+            b"XYZI" if voxels.is_none() => {
+                let count = read_u32(chunk, 0)?;
+                let bytes = chunk
+                    .get(4..4 + count * 4)
+                    .ok_or_else(|| invalid("truncated XYZI chunk"))?;
+                voxels = Some(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|v| ([v[0] as usize, v[1] as usize, v[2] as usize], v[3]))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            b"RGBA" => {
+                // Entry `i` of the chunk holds the colour of palette index `i + 1`.
+                for (i, rgba) in chunk.chunks_exact(4).take(255).enumerate() {
+                    palette[i + 1] = (rgba[0], rgba[1], rgba[2]);
+                }
+            }
+            // Scene graph, materials and any further models are ignored.
+            _ => (),
+        }
+
+        offset = start + content + children;
+    }
+
+    let size = size.ok_or_else(|| invalid("missing SIZE chunk"))?;
+    if size.iter().any(|&s| s > MAX_SIZE) {
+        return Err(invalid(&format!(
+            "the model is {}×{}×{}, larger than the {} tiles per side supported",
+            size[0], size[1], size[2], MAX_SIZE
+        )));
+    }
+    let voxels = voxels.ok_or_else(|| invalid("missing XYZI chunk"))?;
+    if voxels
+        .iter()
+        .any(|(p, _)| p[0] >= size[0] || p[1] >= size[1] || p[2] >= size[2])
+    {
+        return Err(invalid("voxel outside the model bounds"));
+    }
+
+    Ok(Model {
+        size,
+        voxels,
+        palette,
+    })
+}
+
+pub fn load(path: &Path) -> Result<Model> {
+    parse(&std::fs::read(path)?)
+}
+
+/// Block type whose colour is closest to a palette colour, so torches and lava
+/// are placed by painting with their colour.
+fn nearest_block((r, g, b): (u8, u8, u8)) -> u8 {
+    [
+        block::DIRT,
+        block::TORCH,
+        block::LAVA,
+        block::GRASS,
+        block::STONE,
+    ]
+    .into_iter()
+    .min_by_key(|&tile| {
+        let (br, bg, bb) = block::color(tile);
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, br) + d(g, bg) + d(b, bb)
+    })
+    .unwrap()
+}
+
+impl Model {
+    /// Builds a cubic terrain just large enough for the model, standing it
+    /// upright by turning its Z axis into our Y axis.
+    pub fn to_terrain(&self, wrap: bool, style: Style) -> Terrain {
+        let size = self.size.into_iter().max().unwrap_or(1).max(1);
+        let mut tiles = vec![block::AIR; size * size * size];
+        let mut colors = vec![None; size * size * size];
+        for &([x, y, z], index) in self.voxels.iter() {
+            let color = self.palette[index as usize];
+            let i = (size - 1 - y) * size * size + z * size + x;
+            tiles[i] = nearest_block(color);
+            colors[i] = Some(color);
+        }
+        Terrain::from_tiles(size, tiles, colors, wrap, style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], words: &[u32]) -> Vec<u8> {
+        let content: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let mut chunk = id.to_vec();
+        chunk.extend((content.len() as u32).to_le_bytes());
+        chunk.extend(0u32.to_le_bytes());
+        chunk.extend(content);
+        chunk
+    }
+
+    /// A `.vox` file with the given chunks as the children of MAIN.
+    fn vox(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let children: Vec<u8> = chunks.concat();
+        let mut data = b"VOX ".to_vec();
+        data.extend(150u32.to_le_bytes());
+        data.extend(b"MAIN");
+        data.extend(0u32.to_le_bytes());
+        data.extend((children.len() as u32).to_le_bytes());
+        data.extend(children);
+        data
+    }
+
+    fn size(x: u32, y: u32, z: u32) -> Vec<u8> {
+        chunk(b"SIZE", &[x, y, z])
+    }
+
+    /// Voxels as `x, y, z, palette index`.
+    fn xyzi(voxels: &[[u8; 4]]) -> Vec<u8> {
+        let mut words = vec![voxels.len() as u32];
+        words.extend(voxels.iter().map(|&voxel| u32::from_le_bytes(voxel)));
+        chunk(b"XYZI", &words)
+    }
+
+    fn rgba(colors: &[[u8; 4]]) -> Vec<u8> {
+        let mut words: Vec<u32> = colors.iter().map(|&c| u32::from_le_bytes(c)).collect();
+        words.resize(256, 0);
+        chunk(b"RGBA", &words)
+    }
+
+    #[test]
+    fn voxels_stand_upright_in_their_palette_colours() {
+        let data = vox(&[
+            size(3, 3, 3),
+            xyzi(&[[1, 0, 2, 1], [0, 1, 0, 2]]),
+            rgba(&[[200, 40, 30, 255], [10, 20, 250, 255]]),
+        ]);
+        let model = parse(&data).unwrap();
+        assert_eq!(model.size, [3, 3, 3]);
+        assert_eq!(model.palette[1], (200, 40, 30));
+        assert_eq!(model.palette[2], (10, 20, 250));
+
+        // MagicaVoxel's Z becomes our Y, and its Y runs back along our Z.
+        let terrain = model.to_terrain(false, Style::Blocky);
+        assert_eq!(terrain.size(), 3);
+        let mut filled = Vec::new();
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    if terrain.get_unsafe(x, y, z) != block::AIR {
+                        filled.push(([x, y, z], terrain.color(x, y, z)));
+                    }
+                }
+            }
+        }
+        filled.sort();
+        assert_eq!(
+            filled,
+            [
+                ([0, 0, 1], Some((10, 20, 250))),
+                ([1, 2, 2], Some((200, 40, 30))),
+            ]
+        );
+        assert_eq!(terrain.get_unsafe(0, 0, 1), block::STONE);
+    }
+
+    #[test]
+    fn files_without_a_palette_get_the_default_one() {
+        let model = parse(&vox(&[size(1, 1, 1), xyzi(&[[0, 0, 0, 2]])])).unwrap();
+        assert_eq!(model.palette[0], (0, 0, 0));
+        assert_eq!(model.palette[1], (255, 255, 255));
+        assert_eq!(model.palette[2], (255, 255, 204));
+        assert_eq!(model.palette[215], (0, 0, 51));
+        assert_eq!(model.palette[216], (0xee, 0, 0));
+        assert_eq!(model.palette[255], (0x11, 0x11, 0x11));
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let head = vox(&[size(2, 2, 2), xyzi(&[[0, 0, 0, 1], [1, 1, 1, 1]])]);
+        let data = vox(&[
+            size(2, 2, 2),
+            xyzi(&[[0, 0, 0, 1], [1, 1, 1, 1]]),
+            rgba(&[[1, 2, 3, 255]]),
+        ]);
+        assert!(parse(&data).is_ok());
+        // Cut anywhere before the voxels end or inside the palette.
+        for length in (0..head.len()).chain(head.len() + 12..data.len()) {
+            assert!(parse(&data[..length]).is_err(), "{} bytes", length);
+        }
+
+        let short = chunk(b"XYZI", &[3, u32::from_le_bytes([0, 0, 0, 1])]);
+        assert!(parse(&vox(&[size(2, 2, 2), short])).is_err());
+    }
+
+    #[test]
+    fn oversized_and_inconsistent_models_are_rejected() {
+        let voxel = xyzi(&[[0, 0, 0, 1]]);
+        let largest = MAX_SIZE as u32;
+        assert!(parse(&vox(&[size(largest, 1, largest), voxel.clone()])).is_ok());
+        for big in [
+            size(largest + 1, 1, 1),
+            size(1, 1, largest + 1),
+            size(1, u32::MAX, 1),
+        ] {
+            assert!(parse(&vox(&[big, voxel.clone()])).is_err());
+        }
+        assert!(parse(&vox(&[size(2, 2, 2), xyzi(&[[0, 2, 0, 1]])])).is_err());
+        assert!(parse(&vox(&[size(2, 2, 2)])).is_err());
+        assert!(parse(&vox(&[voxel])).is_err());
+        assert!(parse(b"VOX \x96\0\0\0").is_err());
+    }
+}
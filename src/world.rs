@@ -0,0 +1,170 @@
+//! Plain text world files, written by the editor and read with `--world`.
+//!
+//! ```text
+//! size 16
+//! style smooth
+//! wrap
+//! 0000000000000000
+//! 1111100000000000
+//! ...
+//! color 3 4 5 200 180 40
+//! ```
+//!
+//! After the size come `size * size` rows of `size` block ids, one digit per
+//! tile along X, with rows running along Y and then Z. Tiles with a colour of
+//! their own, such as imported voxels, are listed after the rows. Blank lines
+//! and everything after a `#` are ignored. The optional `style` line picks
+//! between `blocky` and `smooth` geometry for the world, and `--smooth` makes
+//! any world smooth. Likewise a `wrap` line or `--wrap` makes it periodic.
+
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+use super::smooth::Style;
+use super::terrain::Terrain;
+
+/// Largest size a world file may have, which keeps a broken or hostile file,
+/// such as one sent by a server, from taking all memory.
+pub const MAX_SIZE: usize = 256;
+
+fn invalid(number: usize, message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", number + 1, message),
+    )
+}
+
+pub fn save(terrain: &Terrain, path: &Path) -> Result<()> {
+    write(terrain, BufWriter::new(std::fs::File::create(path)?))
+}
+
+/// Writes a world in the same format as `save`, for instance to send it to a
+/// client.
+pub fn write<W: Write>(terrain: &Terrain, mut file: W) -> Result<()> {
+    let size = terrain.size();
+    writeln!(file, "# XSection world")?;
+    writeln!(file, "size {}", size)?;
+    let style = match terrain.style() {
+        Style::Blocky => "blocky",
+        Style::Smooth => "smooth",
+    };
+    writeln!(file, "style {}", style)?;
+    if terrain.wrap() {
+        writeln!(file, "wrap")?;
+    }
+
+    let mut colors = Vec::new();
+    for z in 0..size {
+        writeln!(file)?;
+        for y in 0..size {
+            let row: String = (0..size)
+                .map(|x| {
+                    if let Some((r, g, b)) = terrain.color(x, y, z) {
+                        colors.push(format!("color {} {} {} {} {} {}", x, y, z, r, g, b));
+                    }
+                    char::from_digit(terrain.get_unsafe(x, y, z) as u32, 36).unwrap_or('0')
+                })
+                .collect();
+            writeln!(file, "{}", row)?;
+        }
+    }
+
+    if !colors.is_empty() {
+        writeln!(file)?;
+    }
+    for color in colors {
+        writeln!(file, "{}", color)?;
+    }
+    file.flush()
+}
+
+/// Reads a world, made smooth or periodic when either the arguments or the
+/// file ask for it.
+pub fn parse(source: &str, mut wrap: bool, mut style: Style) -> Result<Terrain> {
+    let mut size = None;
+    let mut tiles = Vec::new();
+    let mut colors = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match (words.as_slice(), size) {
+            ([], _) => (),
+            (["style", "blocky"], _) => (),
+            (["style", "smooth"], _) => style = Style::Smooth,
+            (["style", ..], _) => return Err(invalid(number, "expected style blocky or smooth")),
+            (["wrap"], _) => wrap = true,
+            (["size", value], None) => {
+                let value: usize = value
+                    .parse()
+                    .map_err(|_| invalid(number, "expected a size"))?;
+                if value == 0 {
+                    return Err(invalid(number, "the size must be positive"));
+                }
+                let volume = value
+                    .checked_mul(value)
+                    .and_then(|area| area.checked_mul(value))
+                    .filter(|_| value <= MAX_SIZE)
+                    .ok_or_else(|| {
+                        invalid(number, &format!("the size must be at most {}", MAX_SIZE))
+                    })?;
+                size = Some(value);
+                tiles.reserve(volume);
+                colors = vec![None; volume];
+            }
+            (["color", rest @ ..], Some(size)) => {
+                let values = rest
+                    .iter()
+                    .map(|w| w.parse::<usize>())
+                    .collect::<std::result::Result<Vec<usize>, _>>()
+                    .map_err(|_| invalid(number, "expected x y z r g b"))?;
+                match values[..] {
+                    [x, y, z, r, g, b] if x < size && y < size && z < size => {
+                        let channel = |c: usize| u8::try_from(c).unwrap_or(u8::MAX);
+                        colors[z * size * size + y * size + x] =
+                            Some((channel(r), channel(g), channel(b)));
+                    }
+                    _ => return Err(invalid(number, "expected x y z r g b inside the world")),
+                }
+            }
+            ([row], Some(size)) if row.len() == size => {
+                for c in row.chars() {
+                    let tile = c
+                        .to_digit(36)
+                        .ok_or_else(|| invalid(number, "expected block ids"))?;
+                    tiles.push(tile as u8);
+                }
+            }
+            (_, None) => return Err(invalid(number, "expected the size first")),
+            _ => return Err(invalid(number, &format!("cannot parse '{}'", line))),
+        }
+    }
+
+    let size = size.ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing size"))?;
+    if tiles.len() != size * size * size {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("expected {} rows of tiles", size * size),
+        ));
+    }
+    Ok(Terrain::from_tiles(size, tiles, colors, wrap, style))
+}
+
+pub fn load(path: &Path, wrap: bool, style: Style) -> Result<Terrain> {
+    parse(&std::fs::read_to_string(path)?, wrap, style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_too_big_to_hold_are_rejected() {
+        for size in [MAX_SIZE + 1, usize::MAX / 2, usize::MAX] {
+            let source = format!("size {}\n0\n", size);
+            let error = parse(&source, false, Style::Blocky).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+        assert!(parse("size 2\n00\n00\n00\n00\n", false, Style::Blocky).is_ok());
+    }
+}
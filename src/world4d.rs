@@ -0,0 +1,176 @@
+use nalgebra::base::{Matrix4, Vector4};
+
+use super::block;
+
+/// Voxel world with a fourth spatial axis W. It is only ever seen through a
+/// `Hyperplane`, which cuts a regular 3D terrain out of it.
+pub struct Terrain4 {
+    tiles: Vec<u8>,
+    size: usize,
+}
+
+impl Terrain4 {
+    pub fn new(size: usize) -> Terrain4 {
+        let mut terrain = Terrain4 {
+            tiles: vec![block::AIR; size * size * size * size],
+            size,
+        };
+
+        for w in 0..size {
+            for x in 0..size {
+                for z in 0..size {
+                    let xx = (x as f32 / 4.0 - 2.0).sin();
+                    let zz = (z as f32 / 4.0 - 2.0).sin();
+                    let ww = (w as f32 / 3.0).sin();
+                    let height = -(xx * xx) * 2.5 - (zz * zz) * 4.5 + ww * 2.0 + size as f32 / 2.0;
+
+                    let mut depth = 0;
+                    for y in (0..size).rev() {
+                        if (y as f32) >= height {
+                            continue;
+                        }
+                        let tile = match depth {
+                            0 => block::GRASS,
+                            1..=3 => block::DIRT,
+                            _ => block::STONE,
+                        };
+                        terrain.set_unsafe(x, y, z, w, tile);
+                        depth += 1;
+                    }
+                }
+            }
+        }
+
+        // A pillar of lava that only exists within a few slices of W.
+        let centre = size / 2;
+        for w in centre.saturating_sub(1)..(centre + 2).min(size) {
+            for y in 0..size / 3 {
+                terrain.set_unsafe(centre, y, centre, w, block::LAVA);
+            }
+        }
+
+        terrain
+    }
+
+    fn set_unsafe(&mut self, x: usize, y: usize, z: usize, w: usize, value: u8) {
+        let size = self.size;
+        self.tiles[((w * size + z) * size + y) * size + x] = value;
+    }
+
+    pub fn get(&self, x: isize, y: isize, z: isize, w: isize) -> Option<u8> {
+        let size = self.size as isize;
+        if [x, y, z, w].iter().any(|&c| c < 0 || c >= size) {
+            None
+        } else {
+            let size = self.size;
+            let (x, y, z, w) = (x as usize, y as usize, z as usize, w as usize);
+            Some(self.tiles[((w * size + z) * size + y) * size + x])
+        }
+    }
+
+    /// Samples the 3D volume the hyperplane cuts out of the world, one voxel per
+    /// tile of the resulting terrain, in the tile order `Terrain` stores.
+    pub fn slice(&self, hyperplane: &Hyperplane) -> Vec<u8> {
+        let size = self.size;
+        let half = size as f32 / 2.0;
+        let mut tiles = Vec::with_capacity(size * size * size);
+
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    // The hyperplane is centred on its origin in X and Z.
+                    let local = Vector4::new(
+                        x as f32 + 0.5 - half,
+                        y as f32 + 0.5,
+                        z as f32 + 0.5 - half,
+                        0.0,
+                    );
+                    let point = hyperplane.origin + hyperplane.orientation * local;
+                    let tile = self
+                        .get(
+                            point.x.floor() as isize,
+                            point.y.floor() as isize,
+                            point.z.floor() as isize,
+                            point.w.floor() as isize,
+                        )
+                        .unwrap_or(block::AIR);
+                    tiles.push(tile);
+                }
+            }
+        }
+
+        tiles
+    }
+}
+
+/// The 3D subspace through `origin` spanned by the first three columns of
+/// `orientation`, its fourth column being the normal along which it moves.
+/// Slicing handles any orientation, but the controls only ever rotate it within
+/// the X–W plane, so Y stays up and gravity keeps working in the slice.
+pub struct Hyperplane {
+    origin: Vector4<f32>,
+    orientation: Matrix4<f32>,
+}
+
+impl Hyperplane {
+    pub fn new(size: usize) -> Hyperplane {
+        let half = size as f32 / 2.0;
+        Hyperplane {
+            origin: Vector4::new(half, 0.0, half, half),
+            orientation: Matrix4::identity(),
+        }
+    }
+
+    /// Hyperplane through `origin` turned by `angle` radians within the X–W
+    /// plane, which is every pose the controls can reach.
+    pub fn from_pose(origin: Vector4<f32>, angle: f32) -> Hyperplane {
+        let (sin, cos) = angle.sin_cos();
+        let mut orientation = Matrix4::identity();
+        orientation[(0, 0)] = cos;
+        orientation[(0, 3)] = -sin;
+        orientation[(3, 0)] = sin;
+        orientation[(3, 3)] = cos;
+        Hyperplane {
+            origin,
+            orientation,
+        }
+    }
+
+    /// Reads a pose written by `describe`.
+    pub fn parse(value: &str) -> Option<Hyperplane> {
+        let values = value
+            .split(',')
+            .map(|c| c.trim().parse::<f32>().ok())
+            .collect::<Option<Vec<f32>>>()?;
+        match values[..] {
+            [x, y, z, w, degrees] => Some(Hyperplane::from_pose(
+                Vector4::new(x, y, z, w),
+                degrees.to_radians(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// The pose as `x,y,z,w,degrees`, the origin followed by the X–W angle, as
+    /// taken by `--hyperplane`.
+    pub fn describe(&self) -> String {
+        let angle = self.orientation[(3, 0)].atan2(self.orientation[(0, 0)]);
+        let o = self.origin;
+        format!("{},{},{},{},{}", o.x, o.y, o.z, o.w, angle.to_degrees())
+    }
+
+    /// Rotates the hyperplane within the X–W plane, turning the slice towards
+    /// or away from the hidden axis.
+    pub fn rotate(&mut self, delta_time: f32, sign: bool) {
+        let sign = if sign { 1.0 } else { -1.0 };
+        let angle = std::f32::consts::PI / 4.0 * sign * delta_time;
+        self.orientation *= Hyperplane::from_pose(Vector4::zeros(), angle).orientation;
+    }
+
+    /// Moves the hyperplane along its normal.
+    pub fn shift(&mut self, delta_time: f32, sign: bool) {
+        let sign = if sign { 1.0 } else { -1.0 };
+        let normal = self.orientation.column(3).into_owned();
+        self.origin += normal * delta_time * sign * 2.0;
+    }
+}
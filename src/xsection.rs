@@ -1,45 +1,51 @@
-use glium::{implement_vertex, uniform, Program, Surface, VertexBuffer};
-use nalgebra::Matrix4;
+use glium::{implement_vertex, uniform, Surface, VertexBuffer};
 
+use super::assets::Shader;
+use super::atlas::Atlas;
+use super::error::Result;
 use super::plane::Plane;
+use super::sun::Sun;
 use super::terrain::Terrain;
 
 #[derive(Clone, Copy)]
 pub struct Line {
     pub position: [f32; 3],
     pub tex_coord: [f32; 3],
+    pub tile: f32,
+    pub sky_light: f32,
+    pub block_light: f32,
 }
 
-implement_vertex!(Line, position, tex_coord);
+implement_vertex!(Line, position, tex_coord, tile, sky_light, block_light);
 
 pub struct XSection {
     buffer: VertexBuffer<Line>,
+    shadow_buffer: VertexBuffer<Line>,
     indices: glium::index::NoIndices,
-    program: Program,
+    program: Shader,
 }
 
 impl XSection {
-    pub fn new(display: &glium::Display, terrain: &Terrain) -> XSection {
-        let vertex_shader_src = std::fs::read_to_string("shaders/xsection.vert").unwrap();
-        let fragment_shader_src = std::fs::read_to_string("shaders/xsection.frag").unwrap();
-        let geometry_shader_src = std::fs::read_to_string("shaders/xsection.geom").unwrap();
+    pub fn new(display: &glium::Display, terrain: &Terrain) -> Result<XSection> {
+        Ok(XSection {
+            buffer: VertexBuffer::new(display, terrain.lines())?,
+            shadow_buffer: VertexBuffer::new(display, terrain.shadows())?,
+            indices: glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+            program: Shader::load(display, "xsection")?,
+        })
+    }
 
-        let program = Program::from_source(
-            display,
-            &vertex_shader_src,
-            &fragment_shader_src,
-            Some(&geometry_shader_src),
-        )
-        .unwrap();
-        let size = terrain.size() as f32;
+    /// Re-uploads the tile boxes after the terrain was rebuilt.
+    pub fn update(&mut self, display: &glium::Display, terrain: &Terrain) {
+        self.buffer = VertexBuffer::new(display, terrain.lines()).unwrap();
+        self.shadow_buffer = VertexBuffer::new(display, terrain.shadows()).unwrap();
+    }
 
-        XSection {
-            buffer: VertexBuffer::new(display, terrain.lines()).unwrap(),
-            indices: glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
-            program,
-        }
+    pub fn reload(&mut self, display: &glium::Display) {
+        self.program.reload(display);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw<'a, T>(
         &self,
         target: &mut T,
@@ -47,22 +53,43 @@ impl XSection {
         projection: [[f32; 4]; 4],
         plane: &Plane,
         volume: glium::uniforms::Sampler<'a, glium::texture::Texture3d>,
+        atlas: &Atlas,
+        offsets: &[[f32; 3]],
+        sun: &Sun,
     ) where
         T: Surface,
     {
+        let sun_color: [f32; 3] = sun.color.into();
         let transform: [[f32; 4]; 4] = plane.transform().try_inverse().unwrap().into();
-        target
-            .draw(
-                &self.buffer,
-                &self.indices,
-                &self.program,
-                &uniform! {
-                    transform: transform,
-                    projection: projection,
-                    volume: volume
-                },
-                params,
-            )
-            .unwrap();
+
+        // Shadows darken unlit air without hiding it from the depth outline.
+        let mut shadow_params = params.clone();
+        shadow_params.depth.write = false;
+
+        for offset in offsets {
+            for (buffer, shadow, params) in [
+                (&self.buffer, false, params),
+                (&self.shadow_buffer, true, &shadow_params),
+            ] {
+                target
+                    .draw(
+                        buffer,
+                        self.indices,
+                        &self.program,
+                        &uniform! {
+                            transform: transform,
+                            projection: projection,
+                            volume: volume,
+                            atlas: atlas.sampler(),
+                            atlas_tiles: atlas.tiles(),
+                            offset: *offset,
+                            sun_color: sun_color,
+                            shadow: shadow,
+                        },
+                        params,
+                    )
+                    .unwrap();
+            }
+        }
     }
 }